@@ -1,17 +1,12 @@
 // uses the pulldown-cmark crate to parse the markdown
 //
-use crate::constants;
-use docx_rs::Run;
+use crate::manuscript::Span;
 use pulldown_cmark::Options;
-use pulldown_cmark::{Event, Parser, Tag, TextMergeStream};
+use pulldown_cmark::{Event, Parser, Tag, TagEnd, TextMergeStream};
 
-pub fn new_run_with_size() -> Run {
-    Run::new().size(constants::FONT_SIZE)
-}
-
-/// Parse a paragraph of a Markdown document into a list of Runs
-pub fn parse_paragraph(input: &str) -> Vec<Run> {
-    let mut runs: Vec<Run> = vec![];
+/// Parse a paragraph of a Markdown document into a list of Spans
+pub fn parse_spans(input: &str) -> Vec<Span> {
+    let mut spans: Vec<Span> = vec![];
 
     let mut options = Options::empty();
     options.insert(Options::ENABLE_STRIKETHROUGH);
@@ -19,45 +14,68 @@ pub fn parse_paragraph(input: &str) -> Vec<Run> {
     let parser = Parser::new_ext(input, options);
     let iterator = TextMergeStream::new(parser);
 
-    let mut run = new_run_with_size();
+    // How many of each style are open, so that nested emphasis like `*a **b** c*` keeps the outer
+    // style when the inner one ends
+    let (mut italic, mut bold, mut strike) = (0, 0, 0);
+    let styled = |italic: usize, bold: usize, strike: usize| Span {
+        italic: italic > 0,
+        bold: bold > 0,
+        strike: strike > 0,
+        ..Default::default()
+    };
+
+    let mut span = Span::default();
     for event in iterator {
         match event {
             Event::Start(name) => {
-                // save the current run and start a new one with the correct formatting
+                // save the current span and start a new one with the correct formatting
                 match name {
-                    Tag::Paragraph => {}
-                    Tag::Emphasis => {
-                        // It used to be practice to UNDERLINE emphasised text, because typewriters couldn't do italics.
-                        // That's no longer the case with digital text, so we'll use italics instead.
-                        // TODO: Make this configurable?
-                        runs.push(run);
-                        run = new_run_with_size().italic();
-                    }
-                    Tag::Strong => {
-                        runs.push(run);
-                        run = new_run_with_size().bold();
-                    }
-                    Tag::Strikethrough => {
-                        runs.push(run);
-                        run = new_run_with_size().strike();
-                    }
-                    _ => {}
+                    // It used to be practice to UNDERLINE emphasised text, because typewriters couldn't do italics.
+                    // Classic manuscripts still do, which is decided when the manuscript is built.
+                    Tag::Emphasis => italic += 1,
+                    Tag::Strong => bold += 1,
+                    Tag::Strikethrough => strike += 1,
+                    _ => continue,
                 }
+                spans.push(span);
+                span = styled(italic, bold, strike);
             }
             Event::Text(text) => {
                 // TODO: Might be a good place to handle emdash and endash here?
                 // "There’s no need to put spaces around the dash." -- Shunn
-                run = run.add_text(text.to_string());
+                span.text.push_str(&text);
             }
-            Event::End(_) => {
-                // We're at the end of a run, so save what we have and start the next one.
-                runs.push(run);
-                run = new_run_with_size();
+            Event::SoftBreak => {
+                // A soft-wrapped line is part of the same paragraph
+                span.text.push(' ');
+            }
+            Event::HardBreak => {
+                // Keep the current formatting on the other side of the break
+                span.line_break = true;
+                let next = Span {
+                    text: String::new(),
+                    line_break: false,
+                    ..span.clone()
+                };
+                spans.push(span);
+                span = next;
+            }
+            Event::End(name) => {
+                // We're at the end of a span, so save what we have and start the next one, with
+                // whatever styles are still open.
+                match name {
+                    TagEnd::Emphasis => italic -= 1,
+                    TagEnd::Strong => bold -= 1,
+                    TagEnd::Strikethrough => strike -= 1,
+                    _ => {}
+                }
+                spans.push(span);
+                span = styled(italic, bold, strike);
             }
             _ => {}
         }
     }
-    runs
+    spans
 }
 
 #[cfg(test)]
@@ -65,9 +83,35 @@ mod tests {
     use super::*; // Import the parent module's items
 
     #[test]
-    fn test_basic_parsing() {
+    fn test_parse_spans() {
         let input = "Hello world, this is a ~~complicated~~ *very simple* _example_.";
-        let runs = parse_paragraph(input);
-        assert!(runs.len() == 7);
+        let spans = parse_spans(input);
+        assert_eq!(spans.len(), 7);
+        assert!(spans[1].strike);
+        assert_eq!(spans[1].text, "complicated");
+        assert!(spans[3].italic);
+        assert_eq!(spans[3].text, "very simple");
+    }
+
+    #[test]
+    fn test_nested_emphasis() {
+        let spans: Vec<Span> = parse_spans("*a **b** c*")
+            .into_iter()
+            .filter(|span| !span.text.is_empty())
+            .collect();
+        assert_eq!(spans.len(), 3);
+        assert!(spans[0].italic && !spans[0].bold);
+        assert!(spans[1].italic && spans[1].bold);
+        assert_eq!(spans[2].text, " c");
+        assert!(spans[2].italic && !spans[2].bold);
+
+        let spans: Vec<Span> = parse_spans("~~a *b* c~~ d")
+            .into_iter()
+            .filter(|span| !span.text.is_empty())
+            .collect();
+        assert!(spans[1].strike && spans[1].italic);
+        assert!(spans[2].strike && !spans[2].italic);
+        assert_eq!(spans[3].text, " d");
+        assert!(!spans[3].strike);
     }
 }
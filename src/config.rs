@@ -0,0 +1,202 @@
+// The user's configuration file, ~/.md2ms/config.toml
+//
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+use crate::constants;
+use crate::error::Md2msError;
+use crate::{Anonymity, DocumentFormat, PaperSize, Style};
+
+/// The location of the configuration file
+pub const CONFIG_FILE: &str = "~/.md2ms/config.toml";
+
+/// Settings that apply to every manuscript. Command-line arguments take precedence over them.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub struct Config {
+    /// The directory to output the manuscripts to
+    pub output_dir: Option<PathBuf>,
+
+    /// Personally Identifying Information in Markdown format
+    pub pii: Option<String>,
+
+    /// The document format(s) to write the manuscripts in
+    pub formats: Option<Vec<DocumentFormat>>,
+
+    /// The paper size to lay the manuscripts out on
+    pub paper: Option<PaperSize>,
+
+    /// Which styles of manuscript to build
+    pub style: Option<Style>,
+
+    /// Whether to build identified manuscripts, anonymous ones, or both
+    pub anonymity: Option<Anonymity>,
+
+    /// The font size, in points
+    pub font_size: Option<u8>,
+
+    /// The locale used to format numbers, like the word count
+    pub locale: Option<String>,
+
+    /// The marker used to separate scenes, and to set off block quotes. The story's metadata takes
+    /// precedence over this.
+    pub scene_break: Option<String>,
+
+    /// The names a story's metadata file can have, i.e., "metadata.md" or "_story.md". Case
+    /// doesn't matter.
+    pub manifests: Option<Vec<String>>,
+
+    /// The font used for each style
+    pub fonts: Fonts,
+}
+
+/// The font used for each style of manuscript
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+#[serde(default)]
+pub struct Fonts {
+    pub classic: String,
+    pub modern: String,
+}
+
+impl Default for Fonts {
+    fn default() -> Self {
+        Fonts {
+            classic: constants::CLASSIC_FONT.to_string(),
+            modern: constants::DEFAULT_FONT.to_string(),
+        }
+    }
+}
+
+impl Fonts {
+    /// The font to use for the given style
+    pub fn for_style(&self, style: Style) -> String {
+        match style {
+            Style::Classic => self.classic.clone(),
+            _ => self.modern.clone(),
+        }
+    }
+}
+
+impl Config {
+    /// Parse a configuration from a TOML string.
+    pub fn parse(s: &str) -> Result<Self, Md2msError> {
+        let config: Config =
+            toml::from_str(s).map_err(|e| Md2msError::ConfigError(e.message().to_string()))?;
+
+        // The same range as `--font-size`
+        if let Some(points) = config.font_size {
+            if !constants::FONT_SIZES.contains(&(points as i64)) {
+                return Err(Md2msError::ConfigError(format!(
+                    "font_size must be between {} and {} points, not {points}",
+                    constants::FONT_SIZES.start(),
+                    constants::FONT_SIZES.end()
+                )));
+            }
+        }
+        Ok(config)
+    }
+
+    /// Read the configuration file, falling back to the defaults if it doesn't exist.
+    pub fn load() -> Result<Self, Md2msError> {
+        let path = PathBuf::from(shellexpand::tilde(CONFIG_FILE).to_string());
+        if !path.is_file() {
+            return Ok(Config::default());
+        }
+        match std::fs::read_to_string(&path) {
+            Ok(s) => Config::parse(&s),
+            Err(e) => Err(Md2msError::ConfigError(e.to_string())),
+        }
+    }
+
+    /// The settings that will be used, with the defaults filled in for anything not configured.
+    pub fn effective(&self) -> Self {
+        Config {
+            output_dir: Some(self.output_dir.clone().unwrap_or_default()),
+            pii: self.pii.clone(),
+            formats: Some(self.formats.clone().unwrap_or(vec![DocumentFormat::Docx])),
+            paper: Some(self.paper.unwrap_or_default()),
+            style: Some(self.style.unwrap_or_default()),
+            anonymity: Some(self.anonymity.unwrap_or_default()),
+            font_size: Some(self.font_size.unwrap_or((constants::FONT_SIZE / 2) as u8)),
+            locale: Some(
+                self.locale
+                    .clone()
+                    .unwrap_or(constants::DEFAULT_LOCALE.to_string()),
+            ),
+            scene_break: Some(
+                self.scene_break
+                    .clone()
+                    .unwrap_or(constants::SCENE_BREAK.to_string()),
+            ),
+            manifests: Some(self.manifests()),
+            fonts: self.fonts.clone(),
+        }
+    }
+
+    /// The names a story's metadata file can have
+    pub fn manifests(&self) -> Vec<String> {
+        self.manifests
+            .clone()
+            .unwrap_or(vec![constants::MANIFEST.to_string()])
+    }
+
+    /// Render the configuration as TOML.
+    pub fn to_toml(&self) -> Result<String, Md2msError> {
+        toml::to_string(self).map_err(|e| Md2msError::ConfigError(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_config() {
+        let config = Config::parse(
+            r#"
+            output_dir = "~/Writing/Drafts"
+            formats = ["docx", "pdf"]
+            style = "classic"
+            scene_break = "* * *"
+            manifests = ["metadata.md", "_story.md"]
+
+            [fonts]
+            classic = "Courier"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.output_dir, Some(PathBuf::from("~/Writing/Drafts")));
+        assert_eq!(
+            config.formats,
+            Some(vec![DocumentFormat::Docx, DocumentFormat::Pdf])
+        );
+        assert_eq!(config.style, Some(Style::Classic));
+        assert_eq!(config.anonymity, None);
+        assert_eq!(config.scene_break, Some("* * *".to_string()));
+        assert_eq!(config.manifests(), vec!["metadata.md", "_story.md"]);
+        assert_eq!(config.fonts.classic, "Courier");
+        assert_eq!(config.fonts.modern, constants::DEFAULT_FONT);
+
+        assert_eq!(Config::parse("").unwrap(), Config::default());
+        assert!(Config::parse("scene_break = 3").is_err());
+        assert!(Config::parse(r#"style = "baroque""#).is_err());
+
+        // Font sizes are checked like `--font-size`
+        assert_eq!(Config::parse("font_size = 10").unwrap().font_size, Some(10));
+        assert!(Config::parse("font_size = 0").is_err());
+        assert!(Config::parse("font_size = 200").is_err());
+    }
+
+    #[test]
+    fn test_effective_config() {
+        let config = Config::parse(r#"paper = "a4""#).unwrap().effective();
+        assert_eq!(config.paper, Some(PaperSize::A4));
+        assert_eq!(config.style, Some(Style::All));
+        assert_eq!(config.pii, None);
+        assert_eq!(config.manifests, Some(vec!["metadata.md".to_string()]));
+
+        // What `config show` prints can be read back in
+        let toml = config.to_toml().unwrap();
+        assert_eq!(Config::parse(&toml).unwrap(), config);
+    }
+}
@@ -4,6 +4,21 @@
 // For whatever reason, we have to double the font size to get the right size in the docx
 pub const FONT_SIZE: usize = 24;
 
+/// The font sizes that can be chosen, in points
+pub const FONT_SIZES: std::ops::RangeInclusive<i64> = 8..=72;
+
 pub const DEFAULT_FONT: &str = "Times New Roman";
 
+/// The font used for Classic manuscripts, unless configured otherwise
+pub const CLASSIC_FONT: &str = "Courier New";
+
+/// The locale used to format numbers, unless configured otherwise
+pub const DEFAULT_LOCALE: &str = "en-US";
+
+/// The centered marker used to separate scenes, unless configured otherwise
+pub const SCENE_BREAK: &str = "#";
+
+/// The name of a story's metadata file, unless configured otherwise
+pub const MANIFEST: &str = "metadata.md";
+
 pub const FONTS: &[&str] = &["Times New Roman", "Courier New"];
@@ -1,10 +1,16 @@
+use crate::config::{Config, Fonts, CONFIG_FILE};
 use crate::constants;
+use crate::error::Md2msError;
 use crate::markdown::{parse_markdown, parse_pii};
 use crate::metadata::Metadata;
 use crate::pii::PII;
-use crate::utils::{get_base_filename, get_file_basedir, slurp};
-use crate::CompileArgs;
+use crate::report::OutputFormat;
+use crate::utils::{get_base_filename, get_file_basedir, natural_cmp, slurp};
+use crate::word_count::Breakdown;
+use crate::{Anonymity, CompileArgs, ContactBlock, DocumentFormat, Emphasis, PaperSize, Style};
 
+use glob::{MatchOptions, Pattern};
+use std::cmp::Ordering;
 use std::collections::HashMap;
 use std::fs::metadata;
 use std::path::{Path, PathBuf};
@@ -16,9 +22,25 @@ pub struct Context {
     /// Whether the manuscript should be anonymous or identifying
     pub anonymous: bool,
 
+    /// Whether to build identified manuscripts, anonymous ones, or both
+    pub anonymity: Anonymity,
+
+    /// How to break down the word count, if at all
+    pub breakdown: Option<Breakdown>,
+
     /// Whether the manuscript should be formatted in classic style
     pub classic: bool,
 
+    /// The user's configuration file
+    pub config: Config,
+
+    /// Whose contact information goes on the first page, if given on the command line. Otherwise
+    /// it's up to the story.
+    pub contact: Option<ContactBlock>,
+
+    /// How to render emphasis, if given on the command line. Otherwise it depends on the style.
+    pub emphasis: Option<Emphasis>,
+
     /// Whether the manuscript should be formatted in modern style
     // pub modern: bool,
     pub basedir: String,
@@ -28,15 +50,45 @@ pub struct Context {
     /// The font to use for the docx
     pub font: String,
 
-    /// The font size to use for the docx
+    /// The font to use for each style
+    pub fonts: Fonts,
+
+    /// How to print the results of the compile
+    pub format: OutputFormat,
+
+    /// The font size to use for the docx, in half-points
     pub font_size: usize,
 
+    /// The document format(s) to write the manuscript in
+    pub formats: Vec<DocumentFormat>,
+
+    /// The locale used to format numbers, like the word count
+    pub locale: String,
+
+    /// The names a story's metadata file can have, in lowercase
+    pub manifests: Vec<String>,
+
+    /// The paper size to lay the manuscript out on
+    pub paper: PaperSize,
+
     /// Personally Identifiable Information
     pub pii: Option<Document<PII>>,
 
     /// The folder to create the manuscript in.
     pub output_dir: PathBuf,
 
+    /// The scene break marker given on the command line, which overrides the story's metadata
+    pub scene_break: Option<String>,
+
+    /// The scene that was asked for, relative to the story, when counting the words in a single file
+    pub scene: Option<String>,
+
+    /// Which styles of manuscript to build
+    pub style: Style,
+
+    /// Problems found while reading the manuscript, which didn't stop the compile
+    pub warnings: Vec<String>,
+
     /// Only display the word count for the manuscript
     pub word_count: bool,
 }
@@ -49,67 +101,66 @@ impl Clone for Context {
         let mut files: HashMap<String, Document<Metadata>> = HashMap::new();
         for (key, value) in self.files.iter() {
             let doc = Document {
-                metadata: Metadata {
-                    author: value.metadata.author.clone(),
-                    content_warnings: value.metadata.content_warnings.clone(),
-                    heading: value.metadata.heading.clone(),
-                    include: value.metadata.include.clone(),
-                    short_title: value.metadata.short_title.clone(),
-                    short_author: value.metadata.short_author.clone(),
-                    title: value.metadata.title.clone(),
-                },
+                metadata: value.metadata.clone(),
                 content: value.content.clone(),
             };
             files.insert(key.clone(), doc);
         }
 
-        // Clone the PII metadata
-        let mut pii_clone: Document<PII> = Document {
-            metadata: PII {
-                address1: Some(String::new()),
-                address2: Some(String::new()),
-                affiliations: Some(Vec::new()),
-                city: Some(String::new()),
-                country: Some(String::new()),
-                email: Some(String::new()),
-                legal_name: Some(String::new()),
-                postal_code: Some(String::new()),
-                phone: Some(String::new()),
-                state: Some(String::new()),
-            },
-            content: String::new(),
-        };
-
-        if let Some(pii) = &self.pii {
-            pii_clone.metadata.legal_name = pii.metadata.legal_name.clone();
-            pii_clone.metadata.address1 = pii.metadata.address1.clone();
-            pii_clone.metadata.address2 = pii.metadata.address2.clone();
-            pii_clone.metadata.affiliations = pii.metadata.affiliations.clone();
-            pii_clone.metadata.city = pii.metadata.city.clone();
-            pii_clone.metadata.country = pii.metadata.country.clone();
-            pii_clone.metadata.email = pii.metadata.email.clone();
-            pii_clone.metadata.legal_name = pii.metadata.legal_name.clone();
-            pii_clone.metadata.postal_code = pii.metadata.postal_code.clone();
-            pii_clone.metadata.state = pii.metadata.state.clone();
-            pii_clone.metadata.phone = pii.metadata.phone.clone();
-        }
+        // Clone the PII, if there is any. Without it, the title page says that none was supplied.
+        let pii = self.pii.as_ref().map(|pii| Document {
+            metadata: pii.metadata.clone(),
+            content: pii.content.clone(),
+        });
 
         Self {
             anonymous: self.anonymous,
+            anonymity: self.anonymity,
             basedir: self.basedir.clone(),
+            breakdown: self.breakdown,
             classic: self.classic,
+            config: self.config.clone(),
+            contact: self.contact,
+            emphasis: self.emphasis,
             files,
             font: self.font.clone(),
+            fonts: self.fonts.clone(),
+            format: self.format,
             font_size: self.font_size,
-            pii: Some(pii_clone),
+            formats: self.formats.clone(),
+            locale: self.locale.clone(),
+            manifests: self.manifests.clone(),
+            paper: self.paper,
+            pii,
             output_dir: self.output_dir.clone(),
+            scene_break: self.scene_break.clone(),
+            scene: self.scene.clone(),
+            style: self.style,
+            warnings: self.warnings.clone(),
             word_count: self.word_count,
         }
     }
 }
 
 impl Context {
+    /// Build the context from the command line and the user's configuration file. If the file
+    /// can't be read, we carry on with the defaults and warn about it.
     pub fn new(args: &CompileArgs) -> Self {
+        let (config, config_error) = match Config::load() {
+            Ok(config) => (config, None),
+            Err(e) => (Config::default(), Some(e)),
+        };
+
+        let mut s = Self::with_config(args, config);
+        if let Some(e) = config_error {
+            s.warn(format!("Ignoring {CONFIG_FILE}: {e}"));
+        }
+        s
+    }
+
+    /// Build the context from the command line and the given configuration, rather than the
+    /// user's configuration file. Tests use this with `Config::default()`.
+    pub fn with_config(args: &CompileArgs, config: Config) -> Self {
         // let mut basedir = args.filename_or_path.clone();
         let basedir = PathBuf::from(
             shellexpand::tilde(&args.filename_or_path)
@@ -117,44 +168,68 @@ impl Context {
                 .to_owned(),
         );
 
-        // Every author has a different place for this. We just need a sane default
-        let default_output_dir: PathBuf = if args.output_dir.is_some() {
-            PathBuf::from(
-                // TODO: Clean this up so we're not calling a bare unwrap
-                shellexpand::tilde(
-                    &args
-                        .output_dir
-                        .clone()
-                        .unwrap()
-                        .to_string_lossy()
-                        .to_string(),
-                )
-                .to_string()
-                .to_owned(),
-            )
-        } else {
-            PathBuf::new()
-        };
-
         let mut s = Self {
             anonymous: false,
+            anonymity: if args.anonymous.unwrap_or(false) {
+                Anonymity::Anonymous
+            } else {
+                args.anonymity.or(config.anonymity).unwrap_or_default()
+            },
             basedir: basedir.to_string_lossy().to_string().clone(),
+            breakdown: args.breakdown,
             classic: false,
+            config: config.clone(),
+            contact: args.contact,
+            emphasis: args.emphasis,
             files: HashMap::new(),
 
             font: constants::FONTS[0].to_string(),
-            font_size: constants::FONT_SIZE,
+            fonts: match &args.font {
+                Some(font) => Fonts {
+                    classic: font.clone(),
+                    modern: font.clone(),
+                },
+                None => config.fonts.clone(),
+            },
+            format: args.format.unwrap_or_default(),
+            font_size: args
+                .font_size
+                .or(config.font_size)
+                .map(|points| points as usize * 2)
+                .unwrap_or(constants::FONT_SIZE),
+            formats: args
+                .to
+                .clone()
+                .or(config.formats.clone())
+                .unwrap_or(vec![DocumentFormat::Docx]),
+            locale: config
+                .locale
+                .clone()
+                .unwrap_or(constants::DEFAULT_LOCALE.to_string()),
+            manifests: config
+                .manifests()
+                .iter()
+                .map(|name| name.to_lowercase())
+                .collect(),
+            paper: args.paper.or(config.paper).unwrap_or_default(),
             pii: None,
+            // Every author has a different place for this. We just need a sane default
             output_dir: args
                 .output_dir
                 .clone()
-                .unwrap_or(default_output_dir.clone()),
-            word_count: args.word_count.unwrap_or(false),
+                .or(config.output_dir.clone())
+                .unwrap_or_default(),
+            scene_break: args.scene_break.clone(),
+            scene: None,
+            style: args.style.or(config.style).unwrap_or_default(),
+            warnings: Vec::new(),
+            // A breakdown of the word count implies we only want the word count
+            word_count: args.word_count.unwrap_or(false) || args.breakdown.is_some(),
         };
 
         // TODO: read/parse in the PII so that it's available via Context
         if !s.anonymous {
-            if let Some(pii) = args.pii.clone() {
+            if let Some(pii) = args.pii.clone().or(s.config.pii.clone()) {
                 let pii_path = PathBuf::from(shellexpand::tilde(&pii).to_string().to_owned());
 
                 let pii = slurp(pii_path);
@@ -163,11 +238,36 @@ impl Context {
                 }
             }
         }
+        // When counting the words in a single scene, count the rest of its story, too
+        if s.word_count && basedir.is_file() {
+            match find_story(&basedir, &s.manifests) {
+                Ok(Some((story, scene))) => {
+                    s.basedir = story.to_string_lossy().to_string();
+                    s.scene = scene;
+                }
+                Ok(None) => {}
+                Err(e) => s.warn(e.to_string()),
+            }
+        }
+
         s.files = s.read_files(s.basedir.clone());
 
         s
     }
 
+    /// Report a problem that doesn't stop the compile. It's printed straight away, unless the
+    /// results are going to be printed as JSON. A warning that's already been given, i.e. by an
+    /// earlier variant of the manuscript, isn't repeated.
+    pub fn warn(&mut self, warning: String) {
+        if self.warnings.contains(&warning) {
+            return;
+        }
+        if self.format == OutputFormat::Text {
+            println!("{warning}");
+        }
+        self.warnings.push(warning);
+    }
+
     /// Check if a file exists in the context.
     pub fn file_exists(&mut self, filename: String) -> bool {
         self.files.contains_key(&filename)
@@ -184,30 +284,184 @@ impl Context {
     /// Not sure if this is needed anymore, or in its current state.
     /// I decided to move the header metadata into the scene, to make it easier to manage.
     /// I still need to handle "section" metadata, which this function doesn't quite cover.
-    pub fn get_file_metadata(&mut self, file: String) -> Metadata {
-        let mut p = get_file_basedir(format!("{}/{}", self.basedir, file));
-        // Remove the basedir from the path
+    pub fn get_file_metadata(&mut self, file: String) -> Result<Metadata, Md2msError> {
+        let folder = get_file_basedir(format!("{}/{}", self.basedir, file));
 
-        p.push_str("/metadata.md");
-        p = p.trim_start_matches('/').to_string();
+        if let Some(p) = find_manifest(Path::new(&folder), &self.manifests)? {
+            if let Ok(md) = parse_markdown(slurp(p)) {
+                return Ok(md.metadata);
+            }
+        }
+        Ok(Metadata::default())
+    }
 
-        if let Ok(md) = metadata(&p) {
-            if md.is_file() {
-                let md = slurp(p);
-                if let Ok(md) = parse_markdown(md) {
-                    return md.metadata;
+    /// The story's metadata file, if it has one, relative to the story's folder.
+    pub fn manifest(&self) -> Result<Option<String>, Md2msError> {
+        let mut candidates: Vec<&String> = self
+            .files
+            .keys()
+            .filter(|name| !name.contains('/') && self.manifests.contains(&name.to_lowercase()))
+            .collect();
+        candidates.sort();
+        match candidates.len() {
+            0 => Ok(None),
+            1 => Ok(Some(candidates[0].clone())),
+            _ => Err(Md2msError::MultipleManifests(
+                self.basedir.clone(),
+                join_names(&candidates),
+            )),
+        }
+    }
+
+    /// The metadata file of a folder in the story, if it has one
+    pub fn folder_metadata(&self, folder: &str) -> Option<&Metadata> {
+        self.files
+            .iter()
+            .find(|(file, _)| {
+                let (parent, name) = file.rsplit_once('/').unwrap_or(("", file.as_str()));
+                parent == folder && self.manifests.contains(&name.to_lowercase())
+            })
+            .map(|(_, document)| &document.metadata)
+    }
+
+    /// Expand the story's `include` list into the files it names, in order. An entry can be a file,
+    /// a folder, or a glob pattern like `Act 1/**/*.md`. Folders and patterns are expanded in
+    /// natural order, with each folder's metadata file first. Anything `exclude` names is left out,
+    /// as is `manifest`, the metadata file the list came from.
+    pub fn expand_includes(
+        &self,
+        manifest: Option<&str>,
+        include: &[String],
+        exclude: &[String],
+    ) -> Result<Vec<String>, Md2msError> {
+        let excluded = |file: &str| {
+            exclude.iter().any(|entry| {
+                let entry = entry.trim_end_matches('/');
+                file == entry
+                    || file.starts_with(&format!("{entry}/"))
+                    || Pattern::new(entry).is_ok_and(|p| p.matches_with(file, match_options()))
+            })
+        };
+
+        let mut files: Vec<String> = vec![];
+        for entry in include {
+            let entry = entry.trim_end_matches('/');
+            let mut matches: Vec<&String> = if let Some((file, _)) = self.files.get_key_value(entry)
+            {
+                vec![file]
+            } else if entry.contains(['*', '?', '[']) {
+                let pattern = Pattern::new(entry)
+                    .map_err(|e| Md2msError::InvalidPattern(entry.to_string(), e.to_string()))?;
+                self.files
+                    .keys()
+                    .filter(|file| pattern.matches_with(file, match_options()))
+                    .collect()
+            } else {
+                let folder = format!("{entry}/");
+                self.files
+                    .keys()
+                    .filter(|file| file.starts_with(&folder))
+                    .collect()
+            };
+
+            // A metadata file is never part of its own folder or pattern
+            if !self.files.contains_key(entry) {
+                matches.retain(|file| manifest != Some(file.as_str()));
+            }
+            if matches.is_empty() {
+                return Err(Md2msError::FileNotFound(entry.to_string()));
+            }
+
+            matches.sort_by(|a, b| self.compare_paths(a, b));
+            for file in matches {
+                if !excluded(file) && !files.contains(file) {
+                    files.push(file.clone());
                 }
             }
         }
-        Metadata {
-            author: None,
-            short_author: None,
-            heading: None,
-            include: None,
-            short_title: None,
-            title: None,
-            content_warnings: None,
+        Ok(files)
+    }
+
+    /// Resolve the story's `include` list into the files that make up the manuscript, in order. A
+    /// folder's metadata file can have an `include` of its own, relative to its folder, which then
+    /// decides the order of everything in that folder.
+    pub fn resolve_includes(&self, metadata: &Metadata) -> Result<Vec<String>, Md2msError> {
+        let mut files = vec![];
+        let mut manifests: Vec<String> = self.manifest()?.into_iter().collect();
+        self.resolve_manifest("", metadata, &mut manifests, &mut files)?;
+        Ok(files)
+    }
+
+    /// Add the files a metadata file includes, following any nested metadata files. `manifests`
+    /// are the metadata files being resolved, to catch one that includes itself.
+    fn resolve_manifest(
+        &self,
+        folder: &str,
+        metadata: &Metadata,
+        manifests: &mut Vec<String>,
+        files: &mut Vec<String>,
+    ) -> Result<(), Md2msError> {
+        let relative = |entries: &Option<Vec<String>>| -> Vec<String> {
+            entries
+                .iter()
+                .flatten()
+                .map(|entry| relative_to(folder, entry))
+                .collect()
+        };
+        let include = relative(&metadata.include);
+        let exclude = relative(&metadata.exclude);
+
+        // The folders whose order is decided by their own metadata
+        let mut nested: Vec<String> = vec![];
+        let manifest = manifests.last().cloned();
+        for file in self.expand_includes(manifest.as_deref(), &include, &exclude)? {
+            if nested.iter().any(|folder| file.starts_with(folder)) {
+                continue;
+            }
+            if manifests.contains(&file) {
+                manifests.push(file);
+                return Err(Md2msError::IncludeCycle(manifests.join(" -> ")));
+            }
+            if files.contains(&file) {
+                continue;
+            }
+            files.push(file.clone());
+
+            let Some(md) = self.files.get(&file) else {
+                continue;
+            };
+            if md.metadata.include.is_some() {
+                let folder = file
+                    .rsplit_once('/')
+                    .map(|(folder, _)| folder)
+                    .unwrap_or("");
+                nested.push(format!("{folder}/"));
+                manifests.push(file.clone());
+                self.resolve_manifest(folder, &md.metadata.clone(), manifests, files)?;
+                manifests.pop();
+            }
+        }
+        Ok(())
+    }
+
+    /// Order two files the way the story reads: folder by folder in natural order, with each
+    /// folder's metadata file before anything else in it.
+    fn compare_paths(&self, a: &str, b: &str) -> Ordering {
+        let a: Vec<&str> = a.split('/').collect();
+        let b: Vec<&str> = b.split('/').collect();
+        for (i, (x, y)) in a.iter().zip(b.iter()).enumerate() {
+            if x == y {
+                continue;
+            }
+            let x_manifest = i == a.len() - 1 && self.manifests.contains(&x.to_lowercase());
+            let y_manifest = i == b.len() - 1 && self.manifests.contains(&y.to_lowercase());
+            return match (x_manifest, y_manifest) {
+                (true, false) => Ordering::Less,
+                (false, true) => Ordering::Greater,
+                _ => natural_cmp(x, y),
+            };
         }
+        a.len().cmp(&b.len())
     }
 
     /// Determine if the file is Markdown, based on extension.
@@ -250,14 +504,14 @@ impl Context {
                                 md,
                             );
                         } else {
-                            println!("Failed to parse {p}");
+                            self.warn(format!("Failed to parse {p}"));
                         }
                     }
                 } else if path.is_dir() {
                     // Fun with recursion goes here
                     files.extend(self.read_files(spath));
                 } else {
-                    println!("Skipping '{}'", path.display());
+                    self.warn(format!("Skipping '{}'", path.display()));
                 }
             }
         }
@@ -268,3 +522,322 @@ impl Context {
         format!("{}/{}", self.basedir, filename)
     }
 }
+
+/// Find the metadata file in a folder, matching any of the given names regardless of case. It's an
+/// error for there to be more than one, since we can't tell which is meant.
+pub fn find_manifest(folder: &Path, manifests: &[String]) -> Result<Option<PathBuf>, Md2msError> {
+    let Ok(entries) = std::fs::read_dir(folder) else {
+        return Ok(None);
+    };
+    let mut candidates: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.is_file()
+                && path
+                    .file_name()
+                    .is_some_and(|name| manifests.contains(&name.to_string_lossy().to_lowercase()))
+        })
+        .collect();
+    candidates.sort();
+    match candidates.len() {
+        0 => Ok(None),
+        1 => Ok(candidates.pop()),
+        _ => {
+            let names: Vec<String> = candidates
+                .iter()
+                .map(|path| path.file_name().unwrap().to_string_lossy().to_string())
+                .collect();
+            Err(Md2msError::MultipleManifests(
+                folder.display().to_string(),
+                join_names(&names),
+            ))
+        }
+    }
+}
+
+/// How `include` and `exclude` patterns match: `*` stays within a folder, `**` crosses them
+fn match_options() -> MatchOptions {
+    MatchOptions {
+        case_sensitive: true,
+        require_literal_separator: true,
+        require_literal_leading_dot: false,
+    }
+}
+
+/// List file names for an error message, i.e., "Metadata.md" and "index.md".
+fn join_names<S: AsRef<str>>(names: &[S]) -> String {
+    names
+        .iter()
+        .map(|name| format!("\"{}\"", name.as_ref()))
+        .collect::<Vec<String>>()
+        .join(" and ")
+}
+
+/// Walk up from a file to find the story it belongs to. Folders can have a metadata file with an
+/// `include` of their own, so the story is the outermost of the folders above the file with one,
+/// before reaching a folder with no metadata file at all. Returns the story's folder and the file's
+/// path relative to it, unless the file is the story's metadata file.
+pub fn find_story(
+    file: &Path,
+    manifests: &[String],
+) -> Result<Option<(PathBuf, Option<String>)>, Md2msError> {
+    let Ok(file) = std::fs::canonicalize(file) else {
+        return Ok(None);
+    };
+
+    let mut story: Option<(&Path, PathBuf)> = None;
+    for folder in file.ancestors().skip(1) {
+        let Some(manifest) = find_manifest(folder, manifests)? else {
+            if story.is_some() {
+                break;
+            }
+            continue;
+        };
+        if let Ok(md) = parse_markdown(slurp(&manifest)) {
+            // A folder's metadata without an include, like an act's heading, doesn't make a story
+            if md.metadata.include.is_some() {
+                story = Some((folder, manifest));
+            }
+        }
+    }
+
+    let Some((folder, manifest)) = story else {
+        return Ok(None);
+    };
+    if manifest == file {
+        return Ok(Some((folder.to_path_buf(), None)));
+    }
+    let scene = file
+        .strip_prefix(folder)
+        .ok()
+        .map(|p| p.to_string_lossy().to_string());
+    Ok(Some((folder.to_path_buf(), scene)))
+}
+
+/// Resolve a path relative to a folder in the story, i.e., `../Act 2` from `Act 1`.
+fn relative_to(folder: &str, path: &str) -> String {
+    let mut components: Vec<&str> = folder.split('/').filter(|c| !c.is_empty()).collect();
+    for component in path.split('/') {
+        match component {
+            "" | "." => {}
+            ".." => {
+                components.pop();
+            }
+            _ => components.push(component),
+        }
+    }
+    components.join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::TempDir;
+    use clap::Parser;
+
+    #[test]
+    fn test_variant_selection() {
+        let args = CompileArgs::parse_from([
+            "compile",
+            "examples/short",
+            "--style",
+            "modern",
+            "--anonymous",
+            "--font-size",
+            "14",
+        ]);
+        let ctx = Context::with_config(&args, Config::default());
+        assert_eq!(ctx.style.styles(), vec![Style::Modern]);
+        assert_eq!(ctx.anonymity.anonymous(), vec![true]);
+        assert_eq!(ctx.font_size, 28);
+
+        let args = CompileArgs::parse_from([
+            "compile",
+            "examples/short",
+            "--style",
+            "all",
+            "--font",
+            "Courier",
+        ]);
+        let ctx = Context::with_config(&args, Config::default());
+        assert_eq!(ctx.style.styles().len(), 2);
+        assert_eq!(ctx.fonts.for_style(Style::Modern), "Courier");
+
+        assert!(CompileArgs::try_parse_from([
+            "compile",
+            "examples/short",
+            "--anonymous",
+            "--anonymity",
+            "all"
+        ])
+        .is_err());
+    }
+
+    fn manifests() -> Vec<String> {
+        vec![constants::MANIFEST.to_string()]
+    }
+
+    #[test]
+    fn test_find_story() {
+        let (story, scene) = find_story(
+            Path::new("examples/novella_with_parts/Act 2/Chapter 5/scene 2.md"),
+            &manifests(),
+        )
+        .unwrap()
+        .unwrap();
+        assert!(story.ends_with("examples/novella_with_parts"));
+        assert_eq!(scene, Some("Act 2/Chapter 5/scene 2.md".to_string()));
+
+        let (story, scene) = find_story(Path::new("examples/short/metadata.md"), &manifests())
+            .unwrap()
+            .unwrap();
+        assert!(story.ends_with("examples/short"));
+        assert_eq!(scene, None);
+
+        // A standalone story has no metadata.md to find
+        assert!(
+            find_story(Path::new("examples/standalone/standalone.md"), &manifests())
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_find_manifest() {
+        let dir = TempDir::new(
+            "manifest",
+            &[
+                ("Metadata.md", "---\ntitle: Story\n---\n"),
+                ("scene.md", "Words.\n"),
+            ],
+        );
+        let folder = dir.path();
+
+        let names = vec!["metadata.md".to_string(), "index.md".to_string()];
+        assert_eq!(
+            find_manifest(folder, &names).unwrap(),
+            Some(folder.join("Metadata.md"))
+        );
+
+        dir.write("index.md", "---\ntitle: Story\n---\n");
+        match find_manifest(folder, &names) {
+            Err(Md2msError::MultipleManifests(_, names)) => {
+                assert_eq!(names, "\"Metadata.md\" and \"index.md\"")
+            }
+            _ => panic!("Expected an error for two metadata files"),
+        }
+    }
+
+    #[test]
+    fn test_expand_includes() {
+        let args = CompileArgs::parse_from(["compile", "examples/novella_with_parts"]);
+        let ctx = Context::with_config(&args, Config::default());
+        let include = |entries: &[&str]| -> Vec<String> {
+            entries.iter().map(|entry| entry.to_string()).collect()
+        };
+
+        // A folder, in natural order with its metadata first
+        let files = ctx
+            .expand_includes(Some("metadata.md"), &include(&["Act 1"]), &[])
+            .unwrap();
+        assert_eq!(files[0], "Act 1/metadata.md");
+        assert_eq!(files[1], "Act 1/Chapter 1/scene 1.md");
+        assert_eq!(files.len(), 10);
+        assert!(!files.iter().any(|file| file.starts_with("Act 2")));
+
+        // A pattern, less what's excluded, without repeating a file that's already included
+        let files = ctx
+            .expand_includes(
+                Some("metadata.md"),
+                &include(&["Act 1/metadata.md", "Act 1/**/*.md"]),
+                &include(&["Act 1/Chapter 2", "**/scene 3.md"]),
+            )
+            .unwrap();
+        assert_eq!(files[0], "Act 1/metadata.md");
+        assert_eq!(files[1], "Act 1/Chapter 1/scene 1.md");
+        assert_eq!(files.len(), 5);
+        assert!(!files
+            .iter()
+            .any(|file| file.starts_with("Act 1/Chapter 2/")));
+        assert!(!files.iter().any(|file| file.ends_with("scene 3.md")));
+        assert_eq!(
+            files.iter().filter(|f| *f == "Act 1/metadata.md").count(),
+            1
+        );
+
+        // The story's own metadata is never included
+        let files = ctx
+            .expand_includes(Some("metadata.md"), &include(&["*.md"]), &[])
+            .unwrap();
+        assert_eq!(files, vec!["PII.md"]);
+        let files = ctx.expand_includes(Some("metadata.md"), &include(&["Act 4"]), &[]);
+        assert!(matches!(files, Err(Md2msError::FileNotFound(_))));
+    }
+
+    #[test]
+    fn test_resolve_includes() {
+        let args = CompileArgs::parse_from(["compile", "examples/nested"]);
+        let ctx = Context::with_config(&args, Config::default());
+        let metadata = ctx.files.get("metadata.md").unwrap().metadata.clone();
+        assert_eq!(
+            ctx.resolve_includes(&metadata).unwrap(),
+            vec![
+                "Part One/metadata.md",
+                "Part One/Chapter 2/metadata.md",
+                "Part One/Chapter 2/scene 2.md",
+                "Part One/Chapter 2/scene 10.md",
+                "Part One/Chapter 1/metadata.md",
+                "Part One/Chapter 1/morning.md",
+                "Part One/Chapter 1/evening.md",
+                "Part Two/metadata.md",
+                "Part Two/Chapter 3/scene 1.md",
+            ]
+        );
+
+        // A scene belongs to the outermost story, not the folder it's in
+        let (story, scene) = find_story(
+            Path::new("examples/nested/Part One/Chapter 1/evening.md"),
+            &manifests(),
+        )
+        .unwrap()
+        .unwrap();
+        assert!(story.ends_with("examples/nested"));
+        assert_eq!(scene, Some("Part One/Chapter 1/evening.md".to_string()));
+    }
+
+    #[test]
+    fn test_include_cycle() {
+        let dir = TempDir::new(
+            "cycle",
+            &[
+                (
+                    "metadata.md",
+                    "---\ntitle: Story\ninclude:\n- Part One\n---\n",
+                ),
+                (
+                    "Part One/metadata.md",
+                    "---\ninclude:\n- ../metadata.md\n---\n",
+                ),
+            ],
+        );
+
+        let args = CompileArgs::parse_from(["compile", dir.path().to_str().unwrap()]);
+        let ctx = Context::with_config(&args, Config::default());
+        let metadata = ctx.files.get("metadata.md").unwrap().metadata.clone();
+        match ctx.resolve_includes(&metadata) {
+            Err(Md2msError::IncludeCycle(cycle)) => {
+                assert_eq!(cycle, "metadata.md -> Part One/metadata.md -> metadata.md")
+            }
+            _ => panic!("Expected an include cycle"),
+        }
+    }
+
+    #[test]
+    fn test_word_count_for_scene() {
+        let args = CompileArgs::parse_from(["compile", "examples/short/scene2.md", "--word-count"]);
+        let ctx = Context::with_config(&args, Config::default());
+        assert!(ctx.basedir.ends_with("examples/short"));
+        assert_eq!(ctx.scene, Some("scene2.md".to_string()));
+        assert!(ctx.files.contains_key("scene1.md"));
+    }
+}
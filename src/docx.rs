@@ -0,0 +1,417 @@
+// Writes a manuscript as a Microsoft Word (.docx) document
+//
+use chrono::Utc;
+use docx_rs::*;
+use std::io::{Cursor, Seek, Write};
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::context::Context;
+use crate::error::Md2msError;
+use crate::manuscript::{Element, Manuscript, Span};
+use crate::utils::{escape_xml, separate_thousands};
+
+/// An empty run of text, at the given size in half-points
+fn new_run_with_size(size: usize) -> Run {
+    Run::new().size(size)
+}
+
+/// Convert a Span into a docx Run, at the given size in half-points
+fn span_to_run(span: &Span, size: usize) -> Run {
+    let mut run = new_run_with_size(size);
+    if span.italic {
+        run = run.italic();
+    }
+    if span.bold {
+        run = run.bold();
+    }
+    if span.strike {
+        run = run.strike();
+    }
+    if span.underline {
+        run = run.underline("single");
+    }
+    run = run.add_text(span.text.clone());
+    if span.line_break {
+        run = run.add_break(BreakType::TextWrapping);
+    }
+    run
+}
+
+/// The centered marker used to separate scenes.
+fn scene_break(marker: &str, size: usize) -> Paragraph {
+    Paragraph::new()
+        .add_run(Run::new().add_text(marker))
+        .align(AlignmentType::Center)
+        .size(size)
+        .line_spacing(LineSpacing::new().after_lines(100))
+}
+
+/// A double-spaced paragraph of body text.
+fn body_paragraph(spans: &[Span], size: usize) -> Paragraph {
+    let mut p = Paragraph::new()
+        .line_spacing(
+            LineSpacing::new()
+                // https://stackoverflow.com/questions/19719668/how-is-line-spacing-measured-in-ooxml
+                .line_rule(LineSpacingType::Auto)
+                .line(480), // double spaced
+        )
+        // Indent the first line: one half-inch
+        // https://stackoverflow.com/questions/14360183/default-wordml-unit-measurement-pixel-or-point-or-inches
+        // 1.48cm == 0.5826772 inches == 839.05 dxa
+        // According to a Scrivener-compiled document, the indentation should be:
+        // 0.63cm == 0.2480315 inches == 357.16536 dxa
+        .indent(None, Some(SpecialIndentType::FirstLine(357)), None, None);
+    for span in spans {
+        p = p.add_run(span_to_run(span, size));
+    }
+    p
+}
+
+/// A paragraph of a block quote, indented "one half-inch from the left margin"
+/// https://www.shunn.net/format/2010/09/long_quotations_within_your_te.html
+fn block_quote_paragraph(spans: &[Span], size: usize) -> Paragraph {
+    let mut p = Paragraph::new()
+        .align(AlignmentType::Center)
+        .line_spacing(
+            LineSpacing::new()
+                .line_rule(LineSpacingType::Auto)
+                .line(480), // double spaced
+        )
+        .indent(
+            Some(357),
+            Some(SpecialIndentType::FirstLine(357)),
+            None,
+            None,
+        );
+    for span in spans {
+        p = p
+            .add_run(span_to_run(span, size))
+            .align(AlignmentType::Left);
+    }
+    p
+}
+
+/// A double-spaced paragraph with no indent, aligned as given, as in a dedication or an
+/// epigraph's attribution.
+fn aligned_paragraph(spans: &[Span], size: usize, align: AlignmentType) -> Paragraph {
+    let mut p = Paragraph::new().align(align).line_spacing(
+        LineSpacing::new()
+            .line_rule(LineSpacingType::Auto)
+            .line(480), // double spaced
+    );
+    for span in spans {
+        p = p.add_run(span_to_run(span, size));
+    }
+    p
+}
+
+/// A paragraph of an epigraph, indented one half-inch from both margins
+fn epigraph_paragraph(spans: &[Span], size: usize) -> Paragraph {
+    aligned_paragraph(spans, size, AlignmentType::Left).indent(Some(720), None, Some(720), None)
+}
+
+/// The height of a single-spaced line, in twips. The title page uses exact line heights, so the
+/// title lands in the same place whatever the font.
+fn single_line(size: usize) -> u32 {
+    // The size is in half-points, so this is 1.2 times the font size
+    size as u32 * 12
+}
+
+/// Render the body of the manuscript into paragraphs. `opening` is how far below the top margin
+/// a chapter heading starts, in twips.
+fn body(manuscript: &Manuscript, size: usize, opening: u32) -> Vec<Paragraph> {
+    let mut paragraphs: Vec<Paragraph> = vec![];
+
+    // Whether we're at the start of the story or a chapter, where a scene break would be out of place
+    let mut first = true;
+    for element in manuscript.elements() {
+        match element {
+            Element::Heading(heading) => {
+                // A chapter starts on a new page, a third of the way down
+                paragraphs.push(
+                    Paragraph::new()
+                        .add_run(Run::new().add_text(heading).size(size))
+                        .align(AlignmentType::Center)
+                        .page_break_before(true)
+                        .line_spacing(LineSpacing::new().before(opening).after_lines(100)),
+                );
+                first = true;
+                continue;
+            }
+            Element::PageBreak => {
+                // Anything with a page of its own starts where a chapter heading would
+                paragraphs.push(
+                    Paragraph::new()
+                        .size(size)
+                        .page_break_before(true)
+                        .line_spacing(LineSpacing::new().before(opening).after_lines(100)),
+                );
+                first = true;
+                continue;
+            }
+            Element::SceneBreak => paragraphs.push(scene_break(&manuscript.scene_break, size)),
+            Element::Paragraph(spans) => paragraphs.push(body_paragraph(spans, size)),
+            Element::BlockQuote(quote) => {
+                // A block quote is set off with a scene break before and after the text
+                if !first {
+                    paragraphs.push(scene_break(&manuscript.scene_break, size));
+                }
+                for spans in quote {
+                    paragraphs.push(block_quote_paragraph(spans, size));
+                }
+                paragraphs.push(scene_break(&manuscript.scene_break, size));
+            }
+            Element::Epigraph(spans) => paragraphs.push(epigraph_paragraph(spans, size)),
+            Element::Attribution(spans) => {
+                paragraphs.push(aligned_paragraph(spans, size, AlignmentType::Right))
+            }
+            Element::Centered(spans) => {
+                paragraphs.push(aligned_paragraph(spans, size, AlignmentType::Center))
+            }
+            // Signal the end of the story, set like a scene break
+            Element::End => paragraphs.push(scene_break("END", size)),
+        }
+        first = false;
+    }
+    paragraphs
+}
+
+/// Write the manuscript as a Microsoft Word document
+pub fn write_docx<W: Write + Seek>(
+    ctx: &Context,
+    manuscript: &Manuscript,
+    writer: W,
+) -> Result<(), Md2msError> {
+    let title_page = &manuscript.title_page;
+    let size = ctx.font_size;
+    let opening = ctx.paper.opening_twips();
+    let single = LineSpacing::new()
+        .line_rule(LineSpacingType::Exact)
+        .line(single_line(size) as i32);
+
+    // The author's contact information, which is empty if we're anonymous
+    let mut pii = TableCell::new();
+    for line in &title_page.contact {
+        pii = pii.add_paragraph(
+            Paragraph::new()
+                .add_run(Run::new().add_text(line).size(size))
+                .line_spacing(single.clone()),
+        );
+    }
+
+    let mut table = Table::new(vec![TableRow::new(vec![
+        pii,
+        TableCell::new().add_paragraph(
+            Paragraph::new()
+                .add_run(
+                    Run::new()
+                        .add_text(format!(
+                            "about {} words",
+                            separate_thousands(title_page.word_count, &ctx.locale)
+                        ))
+                        .size(size),
+                )
+                .align(AlignmentType::Right)
+                .line_spacing(single),
+        ),
+    ])]);
+
+    // Turn off borders
+    table = table.clear_all_border();
+
+    // Span the width of the page, inside the one inch margins
+    let (page_width, page_height) = ctx.paper.twips();
+    table = table.width((page_width - 1440 * 2) as usize, WidthType::Dxa);
+
+    // The title starts a third of the way down the page, below the contact block, or halfway down
+    // a cover page
+    let cover = manuscript.story_type.cover_page();
+    let top = if cover {
+        ctx.paper.cover_twips()
+    } else {
+        opening
+    };
+    let contact_lines = title_page.contact.len().max(1) as u32;
+    let title = Paragraph::new()
+        .add_run(Run::new().add_text(title_page.title.clone()).size(size))
+        .align(AlignmentType::Center)
+        .line_spacing(
+            LineSpacing::new()
+                .before(top.saturating_sub(contact_lines * single_line(size)))
+                .after_lines(100),
+        );
+
+    let mut byline = Paragraph::new();
+    if let Some(author) = &title_page.author {
+        byline = byline
+            .add_run(Run::new().add_text(format!("by {author}")).size(size))
+            .align(AlignmentType::Center)
+            .line_spacing(LineSpacing::new().after_lines(100));
+    }
+
+    let mut cw = Paragraph::new();
+    if !title_page.content_warnings.is_empty() {
+        cw = cw
+            .add_run(
+                Run::new()
+                    .add_text(format!("CW: {}", title_page.content_warnings.join(", ")))
+                    .size(size),
+            )
+            .align(AlignmentType::Center);
+    }
+
+    let header = Header::new().add_paragraph(
+        Paragraph::new()
+            .add_run(
+                Run::new()
+                    .add_text(manuscript.running_header.clone())
+                    .size(size),
+            )
+            .align(AlignmentType::Right)
+            .add_page_num(PageNum::new()),
+    );
+
+    let mut doc = Docx::new()
+        // .add_style(s)
+        // Add flag to set the default font? TNR is a fine default, but some markets want Courier (and I like it better)
+        .default_fonts(RunFonts::new().ascii(ctx.font.clone()))
+        .page_size(page_width, page_height)
+        .page_margin(
+            PageMargin::new()
+                .top(1440)
+                .bottom(1440)
+                .left(1440)
+                .right(1440)
+                .header(720),
+        )
+        .header(header)
+        .first_header(Header::new())
+        .add_table(table)
+        // Add the title, byline, and content warning (if present)
+        .add_paragraph(title)
+        .add_paragraph(byline)
+        .add_paragraph(cw)
+        .add_paragraph(Paragraph::new())
+        .add_paragraph(Paragraph::new());
+
+    // The cover page isn't counted, so the story starts on page one
+    if cover {
+        doc = doc.page_num_type(PageNumType::new().start(0));
+    }
+
+    // Now we need to add the content of the manuscript
+    for p in body(manuscript, size, opening) {
+        doc = doc.add_paragraph(p);
+    }
+
+    // Build and pack the document, then replace its properties with our own
+    let mut packed = Cursor::new(Vec::new());
+    doc.build()
+        .pack(&mut packed)
+        .map_err(|_| Md2msError::PackError)?;
+    with_properties(packed.get_ref(), manuscript, writer).map_err(|_| Md2msError::PackError)
+}
+
+/// The document's core properties. An anonymous manuscript has no author, so there's nothing in
+/// them to identify who wrote it.
+fn core_xml(manuscript: &Manuscript) -> String {
+    let title = escape_xml(&manuscript.title_page.title);
+    let author = escape_xml(manuscript.title_page.author.as_deref().unwrap_or_default());
+    let now = Utc::now().format("%Y-%m-%dT%H:%M:%SZ");
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<cp:coreProperties xmlns:cp="http://schemas.openxmlformats.org/package/2006/metadata/core-properties" xmlns:dc="http://purl.org/dc/elements/1.1/" xmlns:dcterms="http://purl.org/dc/terms/" xmlns:dcmitype="http://purl.org/dc/dcmitype/" xmlns:xsi="http://www.w3.org/2001/XMLSchema-instance"><dc:title>{title}</dc:title><dc:creator>{author}</dc:creator><cp:lastModifiedBy>{author}</cp:lastModifiedBy><cp:revision>1</cp:revision><dcterms:created xsi:type="dcterms:W3CDTF">{now}</dcterms:created><dcterms:modified xsi:type="dcterms:W3CDTF">{now}</dcterms:modified></cp:coreProperties>
+"#
+    )
+}
+
+/// The document's extended properties, which name the application but never a company
+const APP_XML: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Properties xmlns="http://schemas.openxmlformats.org/officeDocument/2006/extended-properties" xmlns:vt="http://schemas.openxmlformats.org/officeDocument/2006/docPropsVTypes"><Application>md2ms</Application><TotalTime>0</TotalTime><Company></Company></Properties>
+"#;
+
+/// Copy the packed document to the writer, with its core and extended properties set explicitly,
+/// rather than left to whatever docx-rs fills in.
+fn with_properties<W: Write + Seek>(
+    packed: &[u8],
+    manuscript: &Manuscript,
+    writer: W,
+) -> zip::result::ZipResult<()> {
+    let mut archive = ZipArchive::new(Cursor::new(packed))?;
+    let mut zip = ZipWriter::new(writer);
+    let deflated = FileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    for i in 0..archive.len() {
+        let file = archive.by_index_raw(i)?;
+        match file.name() {
+            "docProps/core.xml" | "docProps/app.xml" => continue,
+            _ => zip.raw_copy_file(file)?,
+        }
+    }
+    zip.start_file("docProps/core.xml", deflated)?;
+    zip.write_all(core_xml(manuscript).as_bytes())?;
+    zip.start_file("docProps/app.xml", deflated)?;
+    zip.write_all(APP_XML.as_bytes())?;
+    zip.finish()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::manuscript::TitlePage;
+    use crate::CompileArgs;
+    use clap::Parser;
+    use std::io::Read;
+
+    /// Write the manuscript, and read a file back out of the docx.
+    fn unzip(manuscript: &Manuscript, name: &str) -> String {
+        let ctx = Context::with_config(
+            &CompileArgs::parse_from(["compile", "examples/short"]),
+            Config::default(),
+        );
+        let mut docx = Cursor::new(Vec::new());
+        write_docx(&ctx, manuscript, &mut docx).unwrap();
+
+        let mut archive = ZipArchive::new(docx).unwrap();
+        let mut xml = String::new();
+        archive
+            .by_name(name)
+            .unwrap()
+            .read_to_string(&mut xml)
+            .unwrap();
+        xml
+    }
+
+    #[test]
+    fn test_document_properties() {
+        let mut manuscript = Manuscript {
+            title_page: TitlePage {
+                title: "Fish & Chips".to_string(),
+                author: Some("Jane Quill".to_string()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let core = unzip(&manuscript, "docProps/core.xml");
+        assert!(core.contains("<dc:title>Fish &amp; Chips</dc:title>"));
+        assert!(core.contains("<dc:creator>Jane Quill</dc:creator>"));
+        assert!(core.contains("<cp:lastModifiedBy>Jane Quill</cp:lastModifiedBy>"));
+
+        // An anonymous manuscript has no author, so there's nothing to give it away
+        manuscript.title_page.author = None;
+        let core = unzip(&manuscript, "docProps/core.xml");
+        assert!(core.contains("<dc:creator></dc:creator>"));
+        assert!(core.contains("<cp:lastModifiedBy></cp:lastModifiedBy>"));
+        assert!(!core.contains("Quill"));
+
+        let app = unzip(&manuscript, "docProps/app.xml");
+        assert!(app.contains("<Application>md2ms</Application>"));
+        assert!(app.contains("<Company></Company>"));
+
+        // Everything else docx-rs wrote is still there
+        assert!(!unzip(&manuscript, "word/document.xml").is_empty());
+    }
+}
@@ -6,8 +6,22 @@ use thiserror::Error;
 pub enum Md2msError {
     #[error("Included file not found")]
     FileNotFound(String),
+    #[error("Invalid pattern in `include` or `exclude`, {0}: {1}")]
+    InvalidPattern(String, String),
+    #[error("Metadata files include each other: {0}")]
+    IncludeCycle(String),
     #[error("Metadata is missing `include` key")]
     NoFilesInMetadata,
+    #[error("Found more than one story metadata file in {0}: {1}. Please keep only one.")]
+    MultipleManifests(String, String),
+    #[error("Invalid configuration: {0}")]
+    ConfigError(String),
+    #[error("Couldn't read or write the word count history: {0}")]
+    HistoryError(String),
+    #[error("Couldn't write {0}: {1}")]
+    WriteError(String, String),
+    #[error("Couldn't write the report: {0}")]
+    ReportError(String),
     #[error("Error packing the document")]
     PackError,
     // PackError(#[from] ZipError),
@@ -1,18 +1,32 @@
 pub mod cmark;
+pub mod config;
 pub mod constants;
 pub mod context;
+pub mod docx;
 pub mod error;
+pub mod manuscript;
 pub mod markdown;
 pub mod metadata;
 pub mod obsidian;
 pub mod obsidian_commander;
 pub mod obsidian_shellcommands;
+pub mod odt;
+pub mod pdf;
 pub mod pii;
+pub mod report;
+pub mod stats;
+#[cfg(test)]
+mod test_utils;
 pub mod utils;
+pub mod word_count;
 
-use clap::{ArgAction, Parser, Subcommand};
+use clap::{ArgAction, Parser, Subcommand, ValueEnum};
+use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 
+use crate::report::OutputFormat;
+use crate::word_count::Breakdown;
+
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
 #[command(propagate_version = true)]
@@ -27,9 +41,45 @@ pub enum Commands {
     Compile(CompileArgs),
     /// Install Obsidian integration
     Obsidian(ObsidianArgs),
+    /// Manage the configuration file, ~/.md2ms/config.toml
+    #[command(subcommand)]
+    Config(ConfigCommands),
+    /// Track the word count over time
+    #[command(subcommand)]
+    Stats(StatsCommands),
+}
+
+#[derive(Subcommand)]
+pub enum ConfigCommands {
+    /// Print the effective settings, after merging the configuration file with the defaults
+    Show,
+}
+
+#[derive(Subcommand)]
+pub enum StatsCommands {
+    /// Record the manuscript's word count in its history
+    Log(StatsArgs),
+    /// Show the words written per day and week, and your writing streak
+    History(HistoryArgs),
 }
 
 #[derive(Parser, Debug)]
+pub struct StatsArgs {
+    /// The story's folder, or a file in it
+    pub filename_or_path: String,
+}
+
+#[derive(Parser, Debug)]
+pub struct HistoryArgs {
+    /// The story's folder, or a file in it
+    pub filename_or_path: String,
+
+    /// How many days to show
+    #[arg(long, value_name = "DAYS", default_value_t = 14)]
+    pub days: usize,
+}
+
+#[derive(Parser, Debug, Default)]
 #[command(author, version, about, long_about = None)]
 pub struct CompileArgs {
     /// The file or directory containing the manuscript in Markdown format
@@ -46,6 +96,223 @@ pub struct CompileArgs {
     /// Display the word count and exit.
     #[arg(long, action=ArgAction::SetTrue)]
     pub word_count: Option<bool>,
+
+    /// Display the word count broken down by part, chapter and scene, and exit.
+    #[arg(long, value_enum, value_name = "LAYOUT")]
+    pub breakdown: Option<Breakdown>,
+
+    /// The document format(s) to write the manuscripts in, defaulting to docx.
+    #[arg(long, value_enum, value_name = "FORMAT", num_args = 1..)]
+    pub to: Option<Vec<DocumentFormat>>,
+
+    /// The paper size to lay the manuscript out on, defaulting to US Letter.
+    #[arg(long, value_enum, value_name = "SIZE")]
+    pub paper: Option<PaperSize>,
+
+    /// Which style of manuscript to build, defaulting to all of them.
+    #[arg(long, value_enum, value_name = "STYLE")]
+    pub style: Option<Style>,
+
+    /// Whether to build identified manuscripts, anonymous ones, or all of them (the default).
+    #[arg(long, value_enum, value_name = "ANONYMITY")]
+    pub anonymity: Option<Anonymity>,
+
+    /// Only build anonymous manuscripts. Shorthand for `--anonymity anonymous`.
+    #[arg(long, action=ArgAction::SetTrue, conflicts_with = "anonymity")]
+    pub anonymous: Option<bool>,
+
+    /// The font to use, instead of the style's usual font.
+    #[arg(long, value_name = "FONT")]
+    pub font: Option<String>,
+
+    /// The font size, in points, defaulting to 12.
+    #[arg(long, value_name = "POINTS", value_parser = clap::value_parser!(u8).range(constants::FONT_SIZES))]
+    pub font_size: Option<u8>,
+
+    /// How to render emphasis, overriding the style's default: underlined for Classic, italics for Modern.
+    #[arg(long, value_enum, value_name = "STYLE")]
+    pub emphasis: Option<Emphasis>,
+
+    /// The marker used to separate scenes, e.g. "* * *". An empty marker leaves a blank line.
+    #[arg(long, value_name = "MARKER")]
+    pub scene_break: Option<String>,
+
+    /// Whose contact information goes on the first page, overriding the story's `contact`.
+    #[arg(long, value_enum, value_name = "CONTACT")]
+    pub contact: Option<ContactBlock>,
+
+    /// How to print the results: prose, or a JSON report for scripts.
+    #[arg(long, value_enum, value_name = "FORMAT")]
+    pub format: Option<OutputFormat>,
+}
+
+/// The document formats a manuscript can be written in
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DocumentFormat {
+    /// Microsoft Word
+    Docx,
+    /// OpenDocument Text, i.e., LibreOffice
+    Odt,
+    /// Portable Document Format
+    Pdf,
+}
+
+impl DocumentFormat {
+    /// The file extension used for this format
+    pub fn extension(&self) -> &'static str {
+        match self {
+            DocumentFormat::Docx => "docx",
+            DocumentFormat::Odt => "odt",
+            DocumentFormat::Pdf => "pdf",
+        }
+    }
+}
+
+/// The ways emphasised text can be rendered
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum Emphasis {
+    /// Italics, as a word processor would
+    Italic,
+    /// Underlined, as a typewriter would, per classic Shunn
+    Underline,
+}
+
+/// The manuscript styles that can be built
+#[derive(Clone, Copy, Debug, Default, PartialEq, ValueEnum, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Style {
+    /// Classic Shunn, set in Courier New
+    Classic,
+    /// Modern Shunn, set in Times New Roman
+    Modern,
+    /// Both of the above
+    #[default]
+    All,
+}
+
+impl Style {
+    /// The individual styles to build
+    pub fn styles(&self) -> Vec<Style> {
+        match self {
+            Style::All => vec![Style::Modern, Style::Classic],
+            style => vec![*style],
+        }
+    }
+}
+
+/// Whether to build manuscripts with the author's information, without it, or both
+#[derive(Clone, Copy, Debug, Default, PartialEq, ValueEnum, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Anonymity {
+    /// With the author's name and contact information
+    Identified,
+    /// Without anything identifying the author, for blind submissions
+    Anonymous,
+    /// Both of the above
+    #[default]
+    All,
+}
+
+impl Anonymity {
+    /// Whether each manuscript to build is anonymous
+    pub fn anonymous(&self) -> Vec<bool> {
+        match self {
+            Anonymity::Identified => vec![false],
+            Anonymity::Anonymous => vec![true],
+            Anonymity::All => vec![false, true],
+        }
+    }
+}
+
+/// Whose contact information goes in the top-left of the first page
+#[derive(Clone, Copy, Debug, Default, PartialEq, ValueEnum)]
+pub enum ContactBlock {
+    /// The author's, from the PII
+    #[default]
+    Author,
+    /// The agent's, in place of the author's
+    Agent,
+    /// The author's, with the agent's below it
+    Both,
+}
+
+/// The kinds of story, which decide how the first page is laid out
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum StoryType {
+    Novel,
+    Novella,
+    /// A short story, which is what we assume if the story doesn't say
+    #[default]
+    Short,
+    Flash,
+}
+
+impl StoryType {
+    /// Map the story's `type` to a story type. Anything we don't recognise, like `story`, is a
+    /// short story.
+    pub fn from_name(name: &str) -> Self {
+        match name.trim().to_lowercase().as_str() {
+            "novel" => StoryType::Novel,
+            "novella" => StoryType::Novella,
+            "flash" | "flash fiction" => StoryType::Flash,
+            _ => StoryType::Short,
+        }
+    }
+
+    /// Whether the manuscript gets a cover page of its own, per Shunn's novel format. Otherwise
+    /// the story starts on the first page, below the title.
+    pub fn cover_page(&self) -> bool {
+        matches!(self, StoryType::Novel | StoryType::Novella)
+    }
+}
+
+/// The paper sizes a manuscript can be laid out on
+#[derive(Clone, Copy, Debug, Default, PartialEq, ValueEnum, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PaperSize {
+    /// US Letter, 8.5" x 11"
+    #[default]
+    Letter,
+    /// ISO A4, 210mm x 297mm
+    A4,
+}
+
+impl PaperSize {
+    /// The width and height of the page, in twentieths of a point (dxa)
+    pub fn twips(&self) -> (u32, u32) {
+        match self {
+            PaperSize::Letter => (12240, 15840),
+            PaperSize::A4 => (11906, 16838),
+        }
+    }
+
+    /// How far below the top margin the title and chapter headings start, in twips: a third of the
+    /// way down the text area, inside the one inch margins. It only depends on the page, so it's
+    /// the same for every font.
+    pub fn opening_twips(&self) -> u32 {
+        let (_, height) = self.twips();
+        (height - 1440 * 2) / 3
+    }
+
+    /// How far below the top margin a cover page's title starts, in twips: halfway down the text
+    /// area.
+    pub fn cover_twips(&self) -> u32 {
+        let (_, height) = self.twips();
+        (height - 1440 * 2) / 2
+    }
+
+    /// The width and height of the page, in points
+    pub fn points(&self) -> (f32, f32) {
+        let (width, height) = self.twips();
+        (width as f32 / 20.0, height as f32 / 20.0)
+    }
+
+    /// The width and height of the page, in inches
+    pub fn inches(&self) -> (f32, f32) {
+        let (width, height) = self.twips();
+        (width as f32 / 1440.0, height as f32 / 1440.0)
+    }
 }
 
 #[derive(Parser, Debug)]
@@ -0,0 +1,839 @@
+// A format-neutral model of a manuscript
+//
+// The Markdown is parsed once into this tree, and each of the writers (docx, odt, pdf) renders it.
+use clap::ValueEnum;
+use yaml_front_matter::Document;
+
+use crate::constants;
+use crate::context::Context;
+use crate::error::Md2msError;
+use crate::markdown::flatten_markdown;
+use crate::metadata::Metadata;
+use crate::utils::{round_up, surname};
+use crate::{ContactBlock, Emphasis, StoryType};
+
+/// A manuscript, ready to be rendered
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Manuscript {
+    pub title_page: TitlePage,
+
+    /// The running header printed on every page but the first, without the page number.
+    pub running_header: String,
+
+    /// The centered marker separating scenes, and setting off block quotes
+    pub scene_break: String,
+
+    /// The number of words the author is aiming for, from the story's `word_goal`
+    pub word_goal: Option<usize>,
+
+    /// The kind of story, from the story's `type`. Novels and novellas get a cover page.
+    pub story_type: StoryType,
+
+    /// Anything that comes before the story, like a dedication or an epigraph
+    pub front_matter: Vec<Matter>,
+
+    pub parts: Vec<Part>,
+
+    /// Anything that comes after the story, like an afterword or the author's bio
+    pub back_matter: Vec<Matter>,
+}
+
+/// The contents of the manuscript, as read from the files it includes
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Contents {
+    pub front_matter: Vec<Matter>,
+    pub parts: Vec<Part>,
+    pub back_matter: Vec<Matter>,
+}
+
+/// Everything that appears on the first page, before the story begins
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TitlePage {
+    pub title: String,
+
+    /// The author, used for the byline. This is empty if the manuscript is anonymous.
+    pub author: Option<String>,
+
+    /// The author's contact information. This is empty if the manuscript is anonymous.
+    pub contact: Vec<String>,
+
+    /// The approximate word count, rounded up per Shunn
+    pub word_count: usize,
+
+    pub content_warnings: Vec<String>,
+}
+
+/// A part (or act) of a longer work
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Part {
+    pub heading: Option<String>,
+
+    /// The folder of the metadata file that started the part, relative to the manuscript
+    pub folder: String,
+
+    /// The `word_goal` of the folder's metadata, which started the part
+    pub word_goal: Option<usize>,
+
+    pub chapters: Vec<Chapter>,
+}
+
+/// A chapter, made up of one or more scenes
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Chapter {
+    pub heading: Option<String>,
+
+    /// The folder the chapter starts in, relative to the manuscript
+    pub folder: String,
+
+    /// The `word_goal` of the chapter folder's metadata
+    pub word_goal: Option<usize>,
+
+    pub scenes: Vec<Scene>,
+}
+
+/// A scene, which comes from a single Markdown file. Consecutive scenes are separated by a scene break.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Scene {
+    /// The file the scene was read from, relative to the manuscript
+    pub source: String,
+
+    /// The exact number of words in the scene, not counting comments
+    pub word_count: usize,
+
+    /// The number of words the author is aiming for, from the scene's `word_goal`
+    pub word_goal: Option<usize>,
+
+    pub blocks: Vec<Block>,
+}
+
+/// What a file of front or back matter is, from its `role`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Role {
+    /// A quotation that opens the story, with its attribution
+    Epigraph,
+    /// Centered on a page of its own
+    Dedication,
+    Afterword,
+    AuthorBio,
+}
+
+impl Role {
+    /// Map a file's `role` to a role, if it's one we know.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_lowercase().replace([' ', '-'], "_").as_str() {
+            "epigraph" => Some(Role::Epigraph),
+            "dedication" => Some(Role::Dedication),
+            "afterword" => Some(Role::Afterword),
+            "author_bio" => Some(Role::AuthorBio),
+            _ => None,
+        }
+    }
+
+    /// The heading the matter starts with, unless the file gives one
+    pub fn heading(&self) -> Option<&'static str> {
+        match self {
+            Role::Epigraph | Role::Dedication => None,
+            Role::Afterword => Some("Afterword"),
+            Role::AuthorBio => Some("About the Author"),
+        }
+    }
+}
+
+/// A file of front or back matter, which isn't part of the story or its word count
+#[derive(Clone, Debug, PartialEq)]
+pub struct Matter {
+    pub role: Role,
+
+    /// The file the matter was read from, relative to the manuscript
+    pub source: String,
+
+    pub heading: Option<String>,
+
+    /// Who an epigraph is quoting, and where from
+    pub attribution: Vec<Span>,
+
+    pub blocks: Vec<Block>,
+}
+
+impl Matter {
+    /// Add the matter's elements. `new_page` is whether it has to start on a new page, and the
+    /// result is whether whatever follows it does.
+    fn push_elements<'a>(&'a self, elements: &mut Vec<Element<'a>>, new_page: bool) -> bool {
+        let paragraphs = self.blocks.iter().flat_map(|block| match block {
+            Block::Paragraph(spans) => vec![spans.as_slice()],
+            Block::BlockQuote(paragraphs) => paragraphs.iter().map(|p| p.as_slice()).collect(),
+            Block::SceneBreak => vec![],
+        });
+        match self.role {
+            Role::Epigraph => {
+                if new_page {
+                    elements.push(Element::PageBreak);
+                }
+                elements.extend(paragraphs.map(Element::Epigraph));
+                if !self.attribution.is_empty() {
+                    elements.push(Element::Attribution(&self.attribution));
+                }
+                new_page
+            }
+            Role::Dedication => {
+                elements.push(Element::PageBreak);
+                elements.extend(paragraphs.map(Element::Centered));
+                true
+            }
+            Role::Afterword | Role::AuthorBio => {
+                match self.heading.as_deref().or(self.role.heading()) {
+                    Some(heading) => elements.push(Element::Heading(heading)),
+                    None => elements.push(Element::PageBreak),
+                }
+                elements.extend(self.blocks.iter().map(Element::from));
+                true
+            }
+        }
+    }
+}
+
+/// A block of text within a scene
+#[derive(Clone, Debug, PartialEq)]
+pub enum Block {
+    /// A regular, double-spaced paragraph with a first-line indent
+    Paragraph(Vec<Span>),
+    /// A block quote, made up of one or more paragraphs, set off from the text around it
+    BlockQuote(Vec<Vec<Span>>),
+    /// A scene break written explicitly in the Markdown
+    SceneBreak,
+}
+
+/// A run of text sharing the same inline formatting
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Span {
+    pub text: String,
+    pub italic: bool,
+    pub bold: bool,
+    pub strike: bool,
+    pub underline: bool,
+
+    /// Whether a hard line break follows the text
+    pub line_break: bool,
+}
+
+/// An element of the manuscript, in the order the writers render it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Element<'a> {
+    /// A part or chapter heading, which starts on a new page
+    Heading(&'a str),
+    /// A new page without a heading, for whatever needs a page of its own, like a dedication.
+    /// What follows starts where a heading would.
+    PageBreak,
+    SceneBreak,
+    Paragraph(&'a [Span]),
+    BlockQuote(&'a [Vec<Span>]),
+    /// A paragraph of an epigraph, indented from the left margin
+    Epigraph(&'a [Span]),
+    /// Who an epigraph is quoting, aligned to the right margin
+    Attribution(&'a [Span]),
+    /// A centered paragraph, as in a dedication
+    Centered(&'a [Span]),
+    /// The end of the story, before any back matter
+    End,
+}
+
+impl<'a> From<&'a Block> for Element<'a> {
+    fn from(block: &'a Block) -> Self {
+        match block {
+            Block::Paragraph(spans) => Element::Paragraph(spans),
+            Block::BlockQuote(paragraphs) => Element::BlockQuote(paragraphs),
+            Block::SceneBreak => Element::SceneBreak,
+        }
+    }
+}
+
+/// Render the italic spans of the manuscript as underlined instead.
+fn underline_emphasis(contents: &mut Contents) {
+    let matter = contents
+        .front_matter
+        .iter_mut()
+        .chain(contents.back_matter.iter_mut());
+    let mut paragraphs: Vec<&mut Vec<Span>> = vec![];
+    let mut blocks: Vec<&mut Block> = vec![];
+    for matter in matter {
+        paragraphs.push(&mut matter.attribution);
+        blocks.extend(matter.blocks.iter_mut());
+    }
+    blocks.extend(
+        contents
+            .parts
+            .iter_mut()
+            .flat_map(|part| part.chapters.iter_mut())
+            .flat_map(|chapter| chapter.scenes.iter_mut())
+            .flat_map(|scene| scene.blocks.iter_mut()),
+    );
+    for block in blocks {
+        match block {
+            Block::Paragraph(spans) => paragraphs.push(spans),
+            Block::BlockQuote(quote) => paragraphs.extend(quote.iter_mut()),
+            Block::SceneBreak => {}
+        }
+    }
+    for span in paragraphs.into_iter().flat_map(|spans| spans.iter_mut()) {
+        if span.italic {
+            span.italic = false;
+            span.underline = true;
+        }
+    }
+}
+
+impl Manuscript {
+    /// Build the manuscript from the story's metadata and the files in the context.
+    pub fn new(ctx: &mut Context, document: Document<Metadata>) -> Result<Self, Md2msError> {
+        let metadata = document.metadata.clone();
+        let mut contents = flatten_markdown(ctx, document)?;
+
+        // Classic manuscripts underline emphasis, as a typewriter would, unless told otherwise
+        let emphasis = ctx.emphasis.unwrap_or(if ctx.classic {
+            Emphasis::Underline
+        } else {
+            Emphasis::Italic
+        });
+        if emphasis == Emphasis::Underline {
+            underline_emphasis(&mut contents);
+        }
+
+        let mut title_page = TitlePage {
+            title: metadata.title.clone().unwrap_or_default(),
+            content_warnings: metadata.content_warnings.clone().unwrap_or_default(),
+            ..Default::default()
+        };
+
+        // The command line takes precedence over the story, which takes precedence over the config file
+        let scene_break = ctx
+            .scene_break
+            .clone()
+            .or(metadata.scene_break.clone())
+            .or(ctx.config.scene_break.clone())
+            .unwrap_or(constants::SCENE_BREAK.to_string());
+
+        let short_title = metadata.short_title.clone().unwrap_or_default();
+        let mut running_header = format!("{short_title} / ");
+
+        if !ctx.anonymous {
+            // A pen name on the story takes precedence over the one in the PII. It's used for the
+            // byline and the running header, so the legal name only appears in the contact block.
+            let pen_name = metadata
+                .pen_name
+                .clone()
+                .or(ctx.pii.as_ref().and_then(|my| my.metadata.pen_name.clone()))
+                .filter(|name| !name.trim().is_empty());
+            title_page.author = pen_name.clone().or(metadata.author.clone());
+            if let Some(my) = &ctx.pii {
+                // The command line takes precedence over the story
+                let contact = ctx.contact.unwrap_or(
+                    metadata
+                        .contact
+                        .as_deref()
+                        .and_then(|contact| ContactBlock::from_str(contact, true).ok())
+                        .unwrap_or_default(),
+                );
+                title_page.contact = my.metadata.contact_block(contact, pen_name.as_deref());
+            } else {
+                title_page.contact = vec!["No PII supplied.".to_string()];
+            }
+            // The story's short author is only used with a pen name if it's part of it, so an old
+            // story can't give the legal name away
+            let short_author = match (&pen_name, metadata.short_author.clone()) {
+                (Some(pen_name), Some(short_author)) if pen_name.contains(&short_author) => {
+                    short_author
+                }
+                (Some(pen_name), _) => surname(pen_name),
+                (None, short_author) => short_author.unwrap_or_default(),
+            };
+            running_header = format!("{short_author} / {short_title} / ");
+        }
+
+        let mut manuscript = Manuscript {
+            title_page,
+            running_header,
+            scene_break,
+            word_goal: metadata.word_goal,
+            story_type: metadata
+                .story_type
+                .as_deref()
+                .map(StoryType::from_name)
+                .unwrap_or_default(),
+            front_matter: contents.front_matter,
+            parts: contents.parts,
+            back_matter: contents.back_matter,
+        };
+        manuscript.title_page.word_count = round_up(manuscript.word_count());
+        Ok(manuscript)
+    }
+
+    /// The exact number of words in the manuscript, counting only the files it includes
+    pub fn word_count(&self) -> usize {
+        self.parts
+            .iter()
+            .flat_map(|part| part.chapters.iter())
+            .flat_map(|chapter| chapter.scenes.iter())
+            .map(|scene| scene.word_count)
+            .sum()
+    }
+
+    /// Walk the tree, returning the elements to render in order, with scene breaks between scenes,
+    /// and between chapters that don't have a heading.
+    /// The story opens on a page of its own after a cover page or a dedication, even if it doesn't
+    /// start with a heading, and the back matter follows the end of the story.
+    pub fn elements(&self) -> Vec<Element<'_>> {
+        let mut elements: Vec<Element> = vec![];
+        let mut new_page = self.story_type.cover_page();
+        for matter in &self.front_matter {
+            new_page = matter.push_elements(&mut elements, new_page);
+        }
+
+        let mut story: Vec<Element> = vec![];
+        for part in &self.parts {
+            if let Some(heading) = &part.heading {
+                story.push(Element::Heading(heading));
+            }
+            for chapter in &part.chapters {
+                if let Some(heading) = &chapter.heading {
+                    story.push(Element::Heading(heading));
+                }
+                for scene in &chapter.scenes {
+                    // Scenes are separated by a break, including the first scene of a chapter
+                    // without a heading, so it doesn't run on from the chapter before
+                    if !matches!(story.last(), None | Some(Element::Heading(_))) {
+                        story.push(Element::SceneBreak);
+                    }
+                    story.extend(scene.blocks.iter().map(Element::from));
+                }
+            }
+        }
+        if new_page && !matches!(story.first(), Some(Element::Heading(_))) {
+            elements.push(Element::PageBreak);
+        }
+        elements.extend(story);
+        elements.push(Element::End);
+
+        for matter in &self.back_matter {
+            matter.push_elements(&mut elements, true);
+        }
+        elements
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::markdown::{parse_markdown, parse_pii};
+    use crate::test_utils::TempDir;
+    use crate::utils::slurp;
+    use crate::word_count::WordCount;
+    use crate::CompileArgs;
+    use clap::Parser;
+
+    fn manuscript(path: &str, anonymous: bool) -> Manuscript {
+        let args = CompileArgs::parse_from(["compile", path]);
+        let mut ctx = Context::with_config(&args, Config::default());
+        ctx.anonymous = anonymous;
+        let document = parse_markdown(slurp(format!("{path}/metadata.md"))).unwrap();
+        Manuscript::new(&mut ctx, document).unwrap()
+    }
+
+    #[test]
+    fn test_chapter_folder_goal() {
+        let dir = TempDir::new(
+            "goal",
+            &[
+                (
+                    "metadata.md",
+                    "---\ntitle: Story\ninclude:\n  - One\n  - Two\n---\n",
+                ),
+                ("One/metadata.md", "---\nword_goal: 500\n---\n"),
+                ("One/scene.md", "Three words here.\n"),
+                ("Two/scene.md", "Two words.\n"),
+            ],
+        );
+        let ms = manuscript(&dir.path().to_string_lossy(), false);
+
+        // The folder with a goal is a chapter of its own, even without a heading, and the folder
+        // after it starts another chapter rather than joining it and counting towards its goal
+        let chapters = &ms.parts[0].chapters;
+        assert_eq!(chapters.len(), 2);
+        assert_eq!(chapters[0].heading, None);
+        assert_eq!(chapters[0].word_goal, Some(500));
+        assert_eq!(chapters[1].word_goal, None);
+
+        // The breakdown groups it under the folder's name
+        let words = WordCount::new(&ms);
+        assert_eq!(words.children.len(), 2);
+        assert_eq!(words.children[0].label, "One");
+        assert_eq!(words.children[0].words, 3);
+        assert_eq!(words.children[0].goal, Some(500));
+        assert_eq!(words.children[1].label, "Two/scene.md");
+    }
+
+    #[test]
+    fn test_short_story_structure() {
+        let ms = manuscript("examples/short", false);
+        assert_eq!(ms.title_page.title, "Short Story");
+        assert_eq!(ms.title_page.author, Some("Adam Israel".to_string()));
+        assert_eq!(ms.running_header, "Israel / Short / ");
+        assert_eq!(ms.parts.len(), 1);
+        assert_eq!(ms.parts[0].chapters.len(), 1);
+
+        let scenes = &ms.parts[0].chapters[0].scenes;
+        assert_eq!(scenes.len(), 2);
+        assert_eq!(scenes[0].source, "scene1.md");
+        assert_eq!(scenes[0].blocks.len(), 3);
+
+        // Only the included scenes are counted, not the metadata
+        assert_eq!(ms.word_count(), scenes[0].word_count + scenes[1].word_count);
+        assert_eq!(ms.title_page.word_count, round_up(ms.word_count()));
+    }
+
+    #[test]
+    fn test_novella_structure() {
+        let ms = manuscript("examples/novella_with_parts", false);
+        assert_eq!(ms.parts.len(), 3);
+        assert_eq!(ms.parts[0].heading, Some("Act 1".to_string()));
+        assert_eq!(ms.parts[0].chapters.len(), 3);
+        assert_eq!(
+            ms.parts[2].chapters[2].heading,
+            Some("Chapter 9".to_string())
+        );
+        assert_eq!(ms.parts[2].chapters[2].scenes.len(), 3);
+    }
+
+    #[test]
+    fn test_chapter_numbering() {
+        let ms = manuscript("examples/nested", false);
+        let headings: Vec<Vec<Option<String>>> = ms
+            .parts
+            .iter()
+            .map(|part| part.chapters.iter().map(|c| c.heading.clone()).collect())
+            .collect();
+        assert_eq!(
+            headings,
+            vec![
+                vec![
+                    Some("Chapter One".to_string()),
+                    Some("Chapter Two: The Boats".to_string())
+                ],
+                // Part Two has a template of its own, but the numbering carries on
+                vec![Some("III. Years Later".to_string())],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_elements() {
+        let ms = manuscript("examples/novella_with_parts", false);
+        let elements = ms.elements();
+        assert_eq!(elements[0], Element::Heading("Act 1"));
+        assert_eq!(elements[1], Element::Heading("Chapter 1"));
+        // Three paragraphs per scene, with a scene break between each scene of a chapter
+        assert_eq!(elements[5], Element::SceneBreak);
+        let breaks = elements
+            .iter()
+            .filter(|e| **e == Element::SceneBreak)
+            .count();
+        assert_eq!(breaks, 18);
+    }
+
+    #[test]
+    fn test_chapters_without_headings() {
+        let scene = |text: &str| Scene {
+            blocks: vec![Block::Paragraph(vec![Span {
+                text: text.to_string(),
+                ..Default::default()
+            }])],
+            ..Default::default()
+        };
+        let ms = Manuscript {
+            parts: vec![Part {
+                chapters: vec![
+                    Chapter {
+                        scenes: vec![scene("One")],
+                        ..Default::default()
+                    },
+                    Chapter {
+                        scenes: vec![scene("Two")],
+                        ..Default::default()
+                    },
+                    Chapter {
+                        heading: Some("Three".to_string()),
+                        scenes: vec![scene("Three")],
+                        ..Default::default()
+                    },
+                ],
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+
+        // A break between the chapters without headings, but not before a heading
+        let kinds: Vec<&str> = ms
+            .elements()
+            .iter()
+            .map(|e| match e {
+                Element::Heading(_) => "heading",
+                Element::SceneBreak => "break",
+                Element::Paragraph(_) => "paragraph",
+                Element::End => "end",
+                _ => "other",
+            })
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                "paragraph",
+                "break",
+                "paragraph",
+                "heading",
+                "paragraph",
+                "end"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_anonymous_title_page() {
+        let ms = manuscript("examples/short", true);
+        assert_eq!(ms.title_page.author, None);
+        assert!(ms.title_page.contact.is_empty());
+        assert_eq!(ms.running_header, "Short / ");
+    }
+
+    #[test]
+    fn test_contact_block_without_pii() {
+        let args = CompileArgs::parse_from(["compile", "examples/short"]);
+        let document = || parse_markdown(slurp("examples/short/metadata.md")).unwrap();
+
+        // Each manuscript is built from a clone of the context, which mustn't make up a PII
+        let ctx = Context::with_config(&args, Config::default());
+        let ms = Manuscript::new(&mut ctx.clone(), document()).unwrap();
+        assert_eq!(ms.title_page.contact, vec!["No PII supplied.".to_string()]);
+
+        // Blank fields are left out, rather than leaving empty lines
+        let mut ctx = ctx.clone();
+        ctx.pii = Some(
+            parse_pii(
+                "---\nlegal_name: Adam Israel\naddress1: \"\"\ncity: \"\"\nstate: \"\"\npostal_code: \"\"\nemail: adam@example.com\n---\n"
+                    .to_string(),
+            )
+            .unwrap(),
+        );
+        let ms = Manuscript::new(&mut ctx.clone(), document()).unwrap();
+        assert_eq!(
+            ms.title_page.contact,
+            vec!["Adam Israel".to_string(), "adam@example.com".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_pen_name() {
+        let args = CompileArgs::parse_from(["compile", "examples/short"]);
+        let mut ctx = Context::with_config(&args, Config::default());
+        ctx.pii = Some(
+            parse_pii("---\nlegal_name: Adam Israel\npen_name: A. J. Quill\n---\n".to_string())
+                .unwrap(),
+        );
+        let document = || parse_markdown(slurp("examples/short/metadata.md")).unwrap();
+
+        let ms = Manuscript::new(&mut ctx, document()).unwrap();
+        assert_eq!(ms.title_page.author, Some("A. J. Quill".to_string()));
+        assert_eq!(
+            ms.title_page.contact[0],
+            "Adam Israel (writing as A. J. Quill)"
+        );
+        // The story's short author is the legal surname, so it isn't used
+        assert_eq!(ms.running_header, "Quill / Short / ");
+
+        // The story's pen name wins
+        let mut story = document();
+        story.metadata.pen_name = Some("Adam Israel".to_string());
+        let ms = Manuscript::new(&mut ctx, story).unwrap();
+        assert_eq!(ms.title_page.contact[0], "Adam Israel");
+        assert_eq!(ms.running_header, "Israel / Short / ");
+    }
+
+    #[test]
+    fn test_agent_contact() {
+        let args = CompileArgs::parse_from(["compile", "examples/short"]);
+        let mut ctx = Context::with_config(&args, Config::default());
+        ctx.pii = Some(
+            parse_pii(
+                "---\nlegal_name: Adam Israel\nemail: adam@example.com\nagent:\n  name: Pat Agent\n  agency: Literary Co.\n  email: pat@example.com\n---\n"
+                    .to_string(),
+            )
+            .unwrap(),
+        );
+        let contact = |ctx: &mut Context, contact: Option<&str>| {
+            let mut document = parse_markdown(slurp("examples/short/metadata.md")).unwrap();
+            document.metadata.contact = contact.map(String::from);
+            Manuscript::new(ctx, document).unwrap().title_page.contact
+        };
+
+        assert_eq!(contact(&mut ctx, None), ["Adam Israel", "adam@example.com"]);
+        assert_eq!(
+            contact(&mut ctx, Some("Agent")),
+            ["Pat Agent", "Literary Co.", "pat@example.com"]
+        );
+        assert_eq!(
+            contact(&mut ctx, Some("both")),
+            [
+                "Adam Israel",
+                "adam@example.com",
+                "",
+                "Represented by:",
+                "Pat Agent",
+                "Literary Co.",
+                "pat@example.com"
+            ]
+        );
+
+        // The command line takes precedence over the story
+        ctx.contact = Some(ContactBlock::Author);
+        assert_eq!(
+            contact(&mut ctx, Some("agent")),
+            ["Adam Israel", "adam@example.com"]
+        );
+
+        // Without an agent, it's always the author's
+        ctx.contact = Some(ContactBlock::Agent);
+        ctx.pii.as_mut().unwrap().metadata.agent = None;
+        assert_eq!(contact(&mut ctx, None), ["Adam Israel", "adam@example.com"]);
+    }
+
+    #[test]
+    fn test_classic_underlines_emphasis() {
+        let emphasised = |ms: &Manuscript| -> (usize, usize) {
+            let spans: Vec<&Span> = ms
+                .elements()
+                .into_iter()
+                .flat_map(|e| match e {
+                    Element::Paragraph(spans) => spans.iter().collect(),
+                    _ => vec![],
+                })
+                .collect();
+            (
+                spans.iter().filter(|s| s.italic).count(),
+                spans.iter().filter(|s| s.underline).count(),
+            )
+        };
+
+        let args = CompileArgs::parse_from(["compile", "examples/standalone"]);
+        let document = || parse_markdown(slurp("examples/standalone/standalone.md")).unwrap();
+
+        let mut ctx = Context::with_config(&args, Config::default());
+        let modern = Manuscript::new(&mut ctx, document()).unwrap();
+        let (italic, underline) = emphasised(&modern);
+        assert!(italic > 0);
+        assert_eq!(underline, 0);
+
+        ctx.classic = true;
+        let classic = Manuscript::new(&mut ctx, document()).unwrap();
+        assert_eq!(emphasised(&classic), (0, italic));
+
+        ctx.emphasis = Some(Emphasis::Italic);
+        let classic = Manuscript::new(&mut ctx, document()).unwrap();
+        assert_eq!(emphasised(&classic), (italic, 0));
+    }
+
+    #[test]
+    fn test_scene_break_precedence() {
+        let args = CompileArgs::parse_from(["compile", "examples/short"]);
+        let mut ctx = Context::with_config(&args, Config::default());
+        let document = |scene_break: Option<&str>| {
+            let mut document = parse_markdown(slurp("examples/short/metadata.md")).unwrap();
+            document.metadata.scene_break = scene_break.map(String::from);
+            document
+        };
+
+        ctx.config.scene_break = Some("~".to_string());
+        let ms = Manuscript::new(&mut ctx, document(None)).unwrap();
+        assert_eq!(ms.scene_break, "~");
+
+        let ms = Manuscript::new(&mut ctx, document(Some("* * *"))).unwrap();
+        assert_eq!(ms.scene_break, "* * *");
+
+        ctx.scene_break = Some(String::new());
+        let ms = Manuscript::new(&mut ctx, document(Some("* * *"))).unwrap();
+        assert_eq!(ms.scene_break, "");
+    }
+
+    #[test]
+    fn test_front_and_back_matter() {
+        let roles = |matter: &[Matter]| matter.iter().map(|m| m.role).collect::<Vec<Role>>();
+
+        let ms = manuscript("examples/matter", false);
+        assert_eq!(roles(&ms.front_matter), [Role::Dedication, Role::Epigraph]);
+        assert_eq!(roles(&ms.back_matter), [Role::Afterword, Role::AuthorBio]);
+        assert_eq!(ms.parts[0].chapters.len(), 2);
+
+        // The matter isn't part of the word count
+        assert_eq!(ms.word_count(), 87);
+
+        let elements = ms.elements();
+        assert_eq!(elements[0], Element::PageBreak);
+        assert!(matches!(elements[1], Element::Centered(_)));
+        // The epigraph gets a page of its own, after the cover
+        assert_eq!(elements[2], Element::PageBreak);
+        assert!(matches!(elements[3], Element::Epigraph(_)));
+        assert!(
+            matches!(elements[4], Element::Attribution([dash, title, ..]) if dash.text == "—Mary Shelley, " && title.italic)
+        );
+        assert_eq!(elements[5], Element::Heading("Chapter 1"));
+        let end = elements.iter().position(|e| *e == Element::End).unwrap();
+        assert_eq!(elements[end + 1], Element::Heading("Afterword"));
+        assert_eq!(
+            elements[elements.len() - 2],
+            Element::Heading("About the Author")
+        );
+
+        // The dedication is marked as identifying, and the author's bio is by default
+        let ms = manuscript("examples/matter", true);
+        assert_eq!(roles(&ms.front_matter), [Role::Epigraph]);
+        assert_eq!(roles(&ms.back_matter), [Role::Afterword]);
+    }
+
+    #[test]
+    fn test_matter_placement() {
+        let dir = TempDir::new(
+            "matter",
+            &[
+                (
+                    "metadata.md",
+                    "---\ntitle: Story\ninclude:\n  - part.md\n  - dedication.md\n  - one.md\n  - interlude.md\n  - two.md\n  - bio.md\n---\n",
+                ),
+                ("part.md", "---\nheading: Book One\n---\n"),
+                ("dedication.md", "---\nrole: dedication\n---\nFor you.\n"),
+                ("one.md", "One.\n"),
+                ("interlude.md", "---\nrole: afterword\n---\nMeanwhile.\n"),
+                ("two.md", "Two.\n"),
+                (
+                    "bio.md",
+                    "---\nrole: author_bio\nidentifying: false\n---\nA writer.\n",
+                ),
+            ],
+        );
+
+        let args = CompileArgs::parse_from(["compile", &dir.path().to_string_lossy()]);
+        let mut ctx = Context::with_config(&args, Config::default());
+        ctx.anonymous = true;
+        let document = parse_markdown(slurp(dir.path().join("metadata.md"))).unwrap();
+        let ms = Manuscript::new(&mut ctx, document).unwrap();
+
+        // The dedication comes before the first scene, even though a part has started, and the
+        // bio isn't left out, because it says it doesn't identify the author
+        let roles = |matter: &[Matter]| matter.iter().map(|m| m.role).collect::<Vec<Role>>();
+        assert_eq!(roles(&ms.front_matter), [Role::Dedication]);
+        assert_eq!(roles(&ms.back_matter), [Role::Afterword, Role::AuthorBio]);
+
+        // Matter in the middle of the story is moved after it, with a warning
+        assert_eq!(
+            ctx.warnings,
+            ["interlude.md is in the middle of the story, so it's been moved after the end"]
+        );
+    }
+}
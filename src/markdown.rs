@@ -1,16 +1,26 @@
 // use std::collections::HashMap;
 
-use docx_rs::*;
+use md_word_count::count_words;
 use regex::{Captures, Regex};
 use std::sync::LazyLock;
 use yaml_front_matter::{Document, YamlFrontMatter};
 
-use crate::cmark::parse_paragraph;
-use crate::constants;
+use crate::cmark::parse_spans;
 use crate::context::Context;
 use crate::error::Md2msError;
+use crate::manuscript::{Block, Chapter, Contents, Matter, Part, Role, Scene, Span};
 use crate::metadata::Metadata;
 use crate::pii::PII;
+use crate::utils::{number_to_words, to_roman};
+
+/// A line that marks a scene break: `#`, `\#`, or a thematic break such as `* * *`, `***` or `---`
+static SCENE_BREAK_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^(\\?#|(\*[ \t]*){3,}|(-[ \t]*){3,}|(_[ \t]*){3,})$").unwrap());
+
+/// The start of a line that Markdown would read as a list item or a heading, rather than as more
+/// of the paragraph it's wrapped in
+static BLOCK_START_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^([ \t]*)(\d{1,9}[.)]|[#=+-]|\*[ \t]|\*$)").unwrap());
 
 static LINK_RE: LazyLock<Regex> = LazyLock::new(|| {
     Regex::new(r"\[\[(?:[^\[\]|]*\|)?([^\[\]|]+)\]\]|\[([^\[\]]+)\]\((?:[^()]|\([^()]*\))*\)")
@@ -19,6 +29,11 @@ static LINK_RE: LazyLock<Regex> = LazyLock::new(|| {
 
 /// Strip Markdown comments out of the content
 fn strip_comments(mut content: String) -> String {
+    // A comment on lines of its own is removed along with the blank lines after it, so it doesn't
+    // leave behind a run of blank lines that would be read as a scene break.
+    let re = Regex::new(r"(?ms)^[ \t]*(%%\s+.*?\s+%%|<!--.*?-->)[ \t]*(\n[ \t]*)*(\n|\z)").unwrap();
+    content = Regex::replace_all(&re, content.as_str(), "").to_string();
+
     // Add support single and multi-line %% comment blocks %%
     let re = Regex::new(r"(?s)%%\s+.*?\s+%%").unwrap();
     content = Regex::replace_all(&re, content.as_str(), "").to_string();
@@ -26,9 +41,14 @@ fn strip_comments(mut content: String) -> String {
     content.trim().to_string()
 }
 
+/// Count the words in a Markdown document, as they'll appear in the manuscript, i.e., without comments.
+pub fn word_count(content: &str) -> usize {
+    count_words(&strip_comments(content.to_string()))
+}
+
 /// Convert hyphens to em-dashes
 fn convert_hyphens_to_em_dashes(mut content: String) -> String {
-    let re = Regex::new(r"(\s+--\s+)").unwrap();
+    let re = Regex::new(r"(?m)[ \t]+--([ \t]+|$)").unwrap();
     content = Regex::replace_all(&re, content.as_str(), "—").to_string();
 
     content.trim().to_string()
@@ -36,7 +56,7 @@ fn convert_hyphens_to_em_dashes(mut content: String) -> String {
 
 /// Format em-dashes correctly by stripping the space before/after
 fn format_em_dashes(mut content: String) -> String {
-    let re = Regex::new(r"(\s+—\s+)").unwrap();
+    let re = Regex::new(r"(?m)[ \t]+—([ \t]+|$)").unwrap();
     content = Regex::replace_all(&re, content.as_str(), "—").to_string();
 
     content.trim().to_string()
@@ -50,10 +70,8 @@ fn convert_smart_quotes(mut content: String) -> String {
     content.trim().to_string()
 }
 
-/// Convert the content of a Markdown into a collection of paragraphs.
-fn content_to_paragraphs(mut content: String) -> Vec<Paragraph> {
-    // Pre-process the content
-
+/// Pre-process the content of a Markdown document before it's split into paragraphs.
+fn preprocess_content(mut content: String) -> String {
     // Add support single and multi-line %% comment blocks %%
     content = strip_comments(content);
 
@@ -64,115 +82,201 @@ fn content_to_paragraphs(mut content: String) -> Vec<Paragraph> {
     content = format_em_dashes(content);
 
     // Convert smart quotes to straight quotes
-    content = convert_smart_quotes(content);
-
-    let mut paragraphs: Vec<Paragraph> = vec![];
-    let sep = Paragraph::new()
-        .add_run(Run::new().add_text("#"))
-        .align(AlignmentType::Center)
-        .size(constants::FONT_SIZE)
-        .line_spacing(LineSpacing::new().after_lines(100));
-
-    if content.lines().count() > 0 {
-        let mut blockquote = false;
-
-        content.lines().for_each(|line| {
-            // If the line is empty, skip it. We'll handle line spacing elsewhere.
-            if !line.trim().is_empty() {
-                // need an "is separator function"
-                if line.trim() == "#" {
-                    // This will add the separator for single Markdown documents that explicitly
-                    // include the separator, like the `standalone.md` example.
-                    paragraphs.push(sep.clone());
-                } else if line.starts_with(">") {
-                    // This is a blockquote, so it needs to be handled correctly:
-                    // a centered # before and after the text, and the blockquote should
-                    // be indented "one half-inch from the left margin"
-                    // https://www.shunn.net/format/2010/09/long_quotations_within_your_te.html
-
-                    // The first time we encounter a blockquote, inject a centered # before it
-                    if !blockquote {
-                        let sep = Paragraph::new()
-                            .add_run(Run::new().add_text("#"))
-                            .align(AlignmentType::Center)
-                            .size(constants::FONT_SIZE)
-                            .line_spacing(LineSpacing::new().after_lines(100));
-                        paragraphs.push(sep);
-                    }
-                    blockquote = true;
-
-                    let runs = parse_paragraph(line);
-
-                    let mut p = Paragraph::new()
-                        .align(AlignmentType::Center)
-                        .line_spacing(
-                            LineSpacing::new()
-                                .line_rule(LineSpacingType::Auto)
-                                .line(480), // double spaced
-                        )
-                        .indent(
-                            Some(357),
-                            Some(SpecialIndentType::FirstLine(357)),
-                            None,
-                            None,
-                        );
-                    for run in runs {
-                        p = p.add_run(run).align(AlignmentType::Left);
-                    }
-                    paragraphs.push(p);
-                } else {
-                    // Inject a closing centered # after the blockquote
-                    if blockquote {
-                        let sep = Paragraph::new()
-                            .add_run(Run::new().add_text("#"))
-                            .align(AlignmentType::Center)
-                            .size(constants::FONT_SIZE)
-                            .line_spacing(LineSpacing::new().after_lines(100));
-                        paragraphs.push(sep);
-                        blockquote = false;
-                    }
-                    // Parse the paragraph into runs, which will handle simple formatting.
-                    let runs = parse_paragraph(line);
-
-                    let mut p = Paragraph::new()
-                        .line_spacing(
-                            LineSpacing::new()
-                                // https://stackoverflow.com/questions/19719668/how-is-line-spacing-measured-in-ooxml
-                                .line_rule(LineSpacingType::Auto)
-                                .line(480), // double spaced
-                        )
-                        // Indent the first line: one half-inch
-                        // https://stackoverflow.com/questions/14360183/default-wordml-unit-measurement-pixel-or-point-or-inches
-                        // 1.48cm == 0.5826772 inches == 839.05 dxa
-                        // According to a Scrivener-compiled document, the indentation should be:
-                        // 0.63cm == 0.2480315 inches == 357.16536 dxa
-                        .indent(None, Some(SpecialIndentType::FirstLine(357)), None, None);
-                    for run in runs {
-                        p = p.add_run(run);
-                    }
-                    paragraphs.push(p);
-                }
+    convert_smart_quotes(content)
+}
+
+/// Convert the content of a Markdown into a collection of blocks.
+///
+/// Paragraphs follow CommonMark: consecutive lines are joined into a single paragraph, and a blank
+/// line starts a new one. A line ending in two spaces or a backslash forces a line break.
+///
+/// Scene breaks may be written as `#`, `\#`, `* * *`, `***`, `---`, or two or more blank lines. They
+/// are all normalised to a single [`Block::SceneBreak`].
+fn content_to_blocks(content: String) -> Vec<Block> {
+    let content = preprocess_content(content);
+
+    let mut blocks: Vec<Block> = vec![];
+
+    // The lines of the paragraph we're currently reading
+    let mut paragraph: Vec<&str> = vec![];
+
+    // The paragraphs of the blockquote we're currently reading, and the lines of its current paragraph
+    let mut blockquote: Vec<Vec<Span>> = vec![];
+    let mut quoted: Vec<&str> = vec![];
+
+    // The number of blank lines since the last line of text
+    let mut blank_lines = 0;
+
+    for line in content.lines() {
+        // If the line is empty, it ends the current paragraph. We'll handle line spacing elsewhere.
+        if line.trim().is_empty() {
+            flush_paragraph(&mut paragraph, &mut blocks);
+            flush_quoted(&mut quoted, &mut blockquote);
+            blank_lines += 1;
+            continue;
+        }
+
+        // Two or more blank lines are a scene break
+        if blank_lines > 1 {
+            flush_blockquote(&mut blockquote, &mut blocks);
+            push_scene_break(&mut blocks);
+        }
+        blank_lines = 0;
+
+        if line.starts_with(">") {
+            // This is a blockquote, so it needs to be handled correctly:
+            // a centered # before and after the text, and the blockquote should
+            // be indented "one half-inch from the left margin"
+            // https://www.shunn.net/format/2010/09/long_quotations_within_your_te.html
+            // The writers take care of the formatting; we just need to collect the
+            // paragraphs that belong to it.
+            flush_paragraph(&mut paragraph, &mut blocks);
+
+            if line.trim_start_matches('>').trim().is_empty() {
+                // An empty `>` line separates the paragraphs of the blockquote
+                flush_quoted(&mut quoted, &mut blockquote);
+            } else {
+                quoted.push(line);
             }
-        });
+            continue;
+        }
+
+        // The blockquote has ended
+        flush_quoted(&mut quoted, &mut blockquote);
+        flush_blockquote(&mut blockquote, &mut blocks);
+
+        if SCENE_BREAK_RE.is_match(line.trim()) {
+            flush_paragraph(&mut paragraph, &mut blocks);
+            push_scene_break(&mut blocks);
+        } else {
+            paragraph.push(line);
+        }
     }
-    paragraphs
+
+    flush_paragraph(&mut paragraph, &mut blocks);
+    flush_quoted(&mut quoted, &mut blockquote);
+    flush_blockquote(&mut blockquote, &mut blocks);
+
+    // The scene breaks between files are added when the manuscript is rendered
+    if blocks.last() == Some(&Block::SceneBreak) {
+        blocks.pop();
+    }
+    blocks
 }
 
+/// Add a scene break, unless it would come at the very start of the scene or follow another one.
+fn push_scene_break(blocks: &mut Vec<Block>) {
+    if !matches!(blocks.last(), None | Some(Block::SceneBreak)) {
+        blocks.push(Block::SceneBreak);
+    }
+}
+
+/// Add the blockquote read so far, if any.
+fn flush_blockquote(blockquote: &mut Vec<Vec<Span>>, blocks: &mut Vec<Block>) {
+    if !blockquote.is_empty() {
+        blocks.push(Block::BlockQuote(std::mem::take(blockquote)));
+    }
+}
+
+/// Parse the lines read so far into a paragraph, which will handle simple formatting.
+fn flush_paragraph(lines: &mut Vec<&str>, blocks: &mut Vec<Block>) {
+    if !lines.is_empty() {
+        blocks.push(Block::Paragraph(parse_spans(&join_lines(lines))));
+        lines.clear();
+    }
+}
+
+/// Join the lines of a paragraph. A line that would start a list or a heading is escaped, so it's
+/// read as more of the paragraph, with a space before it, rather than as a block of its own.
+fn join_lines(lines: &[&str]) -> String {
+    lines
+        .iter()
+        .enumerate()
+        .map(|(i, line)| {
+            if i == 0 {
+                return line.to_string();
+            }
+            BLOCK_START_RE
+                .replace(line, |caps: &Captures| {
+                    let (indent, marker) = (&caps[1], &caps[2]);
+                    if marker.starts_with(|c: char| c.is_ascii_digit()) {
+                        let (number, punctuation) = marker.split_at(marker.len() - 1);
+                        format!("{indent}{number}\\{punctuation}")
+                    } else {
+                        format!("{indent}\\{marker}")
+                    }
+                })
+                .to_string()
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/// Parse the blockquote lines read so far into a paragraph of the blockquote.
+fn flush_quoted(lines: &mut Vec<&str>, blockquote: &mut Vec<Vec<Span>>) {
+    if !lines.is_empty() {
+        blockquote.push(parse_spans(&lines.join("\n")));
+        lines.clear();
+    }
+}
+
+/// Flatten the included Markdown files into the parts, chapters and scenes of a manuscript.
+///
+/// A file with a `heading` in its metadata starts a new chapter. If the file has no content of its
+/// own, like the `metadata.md` of an act, the heading starts a new part instead. A folder's
+/// `metadata.md` can also `include` the files in its folder, see `Context::resolve_includes`.
+///
+/// A file with a `role` is front matter if it comes before the first scene of the story, and back
+/// matter if it comes after the last one.
 pub fn flatten_markdown(
     ctx: &mut Context,
     document: Document<Metadata>,
-) -> Result<Vec<Paragraph>, Md2msError> {
-    let mut paragraphs: Vec<Paragraph> = vec![];
-    let mut sep = Paragraph::new();
+) -> Result<Contents, Md2msError> {
+    let mut parts: Vec<Part> = vec![];
+    let mut front_matter: Vec<Matter> = vec![];
+    let mut back_matter: Vec<Matter> = vec![];
 
     // TODO: support variable font sizes (typically 10/12pt.
     // If the metadata doesn't include an include stanza, there's nothing to flatten; it's a standalone document.
     if document.metadata.include.is_none() {
         // println!("No include in metadata");
-        return Ok(content_to_paragraphs(document.content));
+        // Find the file the standalone document was read from
+        let source = ctx
+            .files
+            .iter()
+            .find(|(_, file)| file.content == document.content)
+            .map(|(filename, _)| filename.clone())
+            .unwrap_or_default();
+        let scene = Scene {
+            source,
+            word_count: word_count(&document.content),
+            word_goal: None,
+            blocks: content_to_blocks(document.content),
+        };
+        return Ok(Contents {
+            parts: vec![Part {
+                chapters: vec![Chapter {
+                    scenes: vec![scene],
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }],
+            ..Default::default()
+        });
     }
 
-    for file in document.metadata.include.clone().unwrap() {
+    // The chapter numbering template, which a part can override, and where we are in the story
+    let mut template = document.metadata.chapter_heading.clone();
+    let mut chapter_number = 0;
+    let mut part_folder: Option<String> = None;
+    let mut previous_folder: Option<String> = None;
+
+    // Whether we've read any of the story yet, which decides if matter goes before or after it,
+    // and how much of the back matter has turned out to be in the middle of the story
+    let mut story_started = false;
+    let mut moved = 0;
+
+    for file in ctx.resolve_includes(&document.metadata)? {
         // TODO: need the folders where we might want to show the chapter or act numbers.
         // I've added a per-folder metadata file, but need to handle it.
         // let markdown = ctx.get_file_metadata(file.clone());
@@ -183,49 +287,135 @@ pub fn flatten_markdown(
         }
 
         if let Some(md) = ctx.get_file(file.clone()) {
-            // is this still needed?
-
-            // If there is a heading in the metadata, add it here.
-            if let Some(heading) = md.metadata.heading.clone() {
-                // Reset the separator
-                sep = Paragraph::new();
-                // TODO: Add page break before the heading
-                // Center heading on page?
-                paragraphs.push(
-                    Paragraph::new()
-                        .add_run(Run::new().add_text("").size(constants::FONT_SIZE))
-                        .align(AlignmentType::Center)
-                        .page_break_before(true)
-                        .line_spacing(LineSpacing::new().after_lines(100)),
-                );
-
-                for _ in 0..23 {
-                    paragraphs.push(Paragraph::new());
+            let words = word_count(&md.content);
+            let blocks = content_to_blocks(md.content);
+
+            let folder = file
+                .rsplit_once('/')
+                .map(|(folder, _)| folder.to_string())
+                .unwrap_or_default();
+
+            // Front and back matter is kept apart from the story, and out of anonymous manuscripts
+            // if it identifies the author
+            if let Some(role) = md.metadata.role.as_deref().and_then(Role::from_name) {
+                // An author bio identifies the author unless it says otherwise
+                let identifying = md.metadata.identifying.unwrap_or(role == Role::AuthorBio);
+                if blocks.is_empty() || (ctx.anonymous && identifying) {
+                    continue;
                 }
-                paragraphs.push(
-                    Paragraph::new()
-                        .add_run(Run::new().add_text(heading).size(constants::FONT_SIZE))
-                        .align(AlignmentType::Center)
-                        .line_spacing(LineSpacing::new().after_lines(100)),
-                );
+                let matter = Matter {
+                    role,
+                    source: file,
+                    heading: md.metadata.heading.clone(),
+                    // The attribution follows an em dash, whether or not the author wrote one
+                    attribution: md
+                        .metadata
+                        .attribution
+                        .as_deref()
+                        .map(|a| {
+                            parse_spans(&format!("—{}", a.trim_start_matches(['—', '-', ' '])))
+                        })
+                        .unwrap_or_default(),
+                    blocks,
+                };
+                if story_started {
+                    back_matter.push(matter);
+                } else {
+                    front_matter.push(matter);
+                }
+                continue;
+            }
+
+            // If there is a heading in the metadata of a file without content, start a new part.
+            if let (Some(heading), true) = (md.metadata.heading.clone(), blocks.is_empty()) {
+                parts.push(Part {
+                    heading: Some(heading),
+                    folder: folder.clone(),
+                    word_goal: md.metadata.word_goal,
+                    chapters: vec![],
+                });
+                template = md
+                    .metadata
+                    .chapter_heading
+                    .clone()
+                    .or(document.metadata.chapter_heading.clone());
+                part_folder = Some(folder.clone());
+            }
+
+            if blocks.is_empty() {
+                continue;
+            }
+
+            // Matter can't go in the middle of the story, so any that came before this scene
+            // follows the end instead
+            for matter in &back_matter[moved..] {
+                ctx.warn(format!(
+                    "{} is in the middle of the story, so it's been moved after the end",
+                    matter.source
+                ));
             }
+            moved = back_matter.len();
+            story_started = true;
 
-            // If there is a separator, add it to the list of paragraphs to create a visible
-            // break between scenes.
-            if !sep.raw_text().is_empty() {
-                paragraphs.push(sep.clone());
+            if parts.is_empty() {
+                parts.push(Part::default());
             }
 
-            let mut p = content_to_paragraphs(md.content);
-            if !p.is_empty() {
-                // Add all the paragraphs to the current list of paragraphs
-                paragraphs.append(&mut p);
+            // A chapter starts with a heading, a file marked as a chapter, or, if chapters are
+            // numbered or a folder has a word goal of its own, the first file in a chapter's
+            // folder. The folder after one with a goal starts a chapter too, so its scenes aren't
+            // counted towards that goal.
+            let new_folder = !folder.is_empty()
+                && previous_folder.as_ref() != Some(&folder)
+                && part_folder.as_ref() != Some(&folder);
+            let folder_goal = if new_folder {
+                ctx.folder_metadata(&folder)
+                    .and_then(|metadata| metadata.word_goal)
+            } else {
+                None
+            };
+            let after_goal = parts
+                .last()
+                .and_then(|part| part.chapters.last())
+                .is_some_and(|chapter| chapter.word_goal.is_some());
+            if md.metadata.heading.is_some()
+                || md.metadata.chapter == Some(true)
+                || (new_folder && (template.is_some() || folder_goal.is_some() || after_goal))
+            {
+                chapter_number += 1;
+                let title = md.metadata.chapter_title.clone().or(ctx
+                    .folder_metadata(&folder)
+                    .and_then(|metadata| metadata.chapter_title.clone()));
+                let heading = match (md.metadata.heading.clone(), &template) {
+                    (Some(heading), _) => Some(with_title(heading, title)),
+                    (None, Some(template)) => {
+                        Some(chapter_heading(template, chapter_number, title))
+                    }
+                    (None, None) => title,
+                };
+                if let Some(part) = parts.last_mut() {
+                    part.chapters.push(Chapter {
+                        heading,
+                        folder: folder.clone(),
+                        word_goal: folder_goal,
+                        scenes: vec![],
+                    });
+                }
+            }
+            previous_folder = Some(folder);
 
-                sep = Paragraph::new()
-                    .add_run(Run::new().add_text("#"))
-                    .align(AlignmentType::Center)
-                    .size(constants::FONT_SIZE)
-                    .line_spacing(LineSpacing::new().after_lines(100));
+            if let Some(part) = parts.last_mut() {
+                if part.chapters.is_empty() {
+                    part.chapters.push(Chapter::default());
+                }
+                if let Some(chapter) = part.chapters.last_mut() {
+                    chapter.scenes.push(Scene {
+                        source: file,
+                        word_count: words,
+                        word_goal: md.metadata.word_goal,
+                        blocks,
+                    });
+                }
             }
         } else {
             // If a file is noted to be included, but we can't find it, that's a problem.
@@ -233,7 +423,36 @@ pub fn flatten_markdown(
         }
     }
 
-    Ok(paragraphs)
+    Ok(Contents {
+        front_matter,
+        parts,
+        back_matter,
+    })
+}
+
+/// Fill in a chapter heading template, i.e., "Chapter {n:words}" becomes "Chapter Three". If the
+/// chapter has a title and the template doesn't say where it goes, it follows the number.
+pub fn chapter_heading(template: &str, n: usize, title: Option<String>) -> String {
+    let heading = template
+        .replace("{n:words}", &number_to_words(n))
+        .replace("{n:roman}", &to_roman(n))
+        .replace("{n}", &n.to_string());
+    if heading.contains("{title}") {
+        let title = title.unwrap_or_default();
+        return heading
+            .replace("{title}", &title)
+            .trim_end_matches([':', '.', '-', '—', ' '])
+            .to_string();
+    }
+    with_title(heading, title)
+}
+
+/// Add the chapter's title to its heading, i.e., "Chapter 3: The Flood".
+fn with_title(heading: String, title: Option<String>) -> String {
+    match title {
+        Some(title) => format!("{heading}: {title}"),
+        None => heading,
+    }
 }
 
 /// Parse the PII document
@@ -241,6 +460,8 @@ pub fn parse_pii(md: String) -> Result<Document<PII>, &'static str> {
     let mut pii = Document {
         metadata: PII {
             legal_name: None,
+            pen_name: None,
+            agent: None,
             address1: None,
             address2: None,
             city: None,
@@ -265,15 +486,7 @@ pub fn parse_pii(md: String) -> Result<Document<PII>, &'static str> {
 /// Parse the markdown document
 pub fn parse_markdown(md: String) -> Result<Document<Metadata>, &'static str> {
     let mut document = Document {
-        metadata: Metadata {
-            author: None,
-            content_warnings: None,
-            heading: None,
-            include: None,
-            short_title: None,
-            short_author: None,
-            title: None,
-        },
+        metadata: Metadata::default(),
         content: "".to_string(),
     };
 
@@ -300,9 +513,18 @@ pub fn parse_markdown(md: String) -> Result<Document<Metadata>, &'static str> {
 }
 
 /// Trim double-spaces from a string.
+///
+/// Two or more spaces at the end of a line are kept, because they mark a hard line break.
 fn trim_doublespace(s: &str) -> String {
-    let re = Regex::new(r"[ ]+").unwrap();
-    re.replace_all(s, " ").to_string()
+    let re = Regex::new(r"([ ]+)(\n?)").unwrap();
+    re.replace_all(s, |caps: &Captures| {
+        if !caps[2].is_empty() && caps[1].len() > 1 {
+            "  \n".to_string()
+        } else {
+            format!(" {}", &caps[2])
+        }
+    })
+    .to_string()
 }
 
 // Replace all links in a string with their target text
@@ -321,6 +543,7 @@ fn trim_links(s: &str) -> String {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::utils::slurp;
     const SIMPLE_MARKDOWN_YFM: &str = r#"
     ---
     author: 'Adam Israel'
@@ -353,6 +576,12 @@ mod tests {
         assert!(content.is_empty());
     }
 
+    #[test]
+    fn test_word_count_skips_comments() {
+        let content = "%% A note to myself %%\nOne two three.\n\n%%\nMore notes.\n%%\n\nFour five.";
+        assert_eq!(word_count(content), 5);
+    }
+
     #[test]
     fn test_convert_smart_quotes() {
         let content = convert_smart_quotes("“Go home,” Gabriel said.".to_string());
@@ -375,12 +604,122 @@ mod tests {
         assert!(content == "This is a test—only a test—he was told.");
     }
 
+    #[test]
+    fn test_em_dash_at_end_of_paragraph() {
+        // The dash stays on its line, rather than eating the blank lines of a scene break
+        let content = "He was told --\n\n\nShe left —\n\nThe end.";
+        let content = format_em_dashes(convert_hyphens_to_em_dashes(content.to_string()));
+        assert_eq!(content, "He was told—\n\n\nShe left—\n\nThe end.");
+
+        let blocks = content_to_blocks(content);
+        assert_eq!(blocks.len(), 4);
+        assert_eq!(blocks[1], Block::SceneBreak);
+    }
+
     #[test]
     fn test_trim_doublespace() {
         let s = "This is a test.  This is only a test.\nIf this were an actual emergency, you would be instructed where to go and what to do.";
         assert!(trim_doublespace(s) == "This is a test. This is only a test.\nIf this were an actual emergency, you would be instructed where to go and what to do.");
     }
 
+    #[test]
+    fn test_trim_doublespace_keeps_hard_breaks() {
+        let s = "A line  with a hard break   \nand a  soft one \nend.";
+        assert_eq!(
+            trim_doublespace(s),
+            "A line with a hard break  \nand a soft one \nend."
+        );
+    }
+
+    #[test]
+    fn test_soft_wrapped_lines_join() {
+        let content =
+            "This paragraph is\nhard-wrapped at\nan arbitrary column.\n\nThis is the second.";
+        let blocks = content_to_blocks(content.to_string());
+        assert_eq!(blocks.len(), 2);
+        if let Block::Paragraph(spans) = &blocks[0] {
+            let text: String = spans.iter().map(|s| s.text.clone()).collect();
+            assert_eq!(
+                text,
+                "This paragraph is hard-wrapped at an arbitrary column."
+            );
+        } else {
+            panic!("Expected a paragraph");
+        }
+    }
+
+    #[test]
+    fn test_wrapped_lines_that_look_like_blocks() {
+        let content = "She counted\n- one, two\n1. three\n# four\n* five and *six*.";
+        let blocks = content_to_blocks(content.to_string());
+        assert_eq!(blocks.len(), 1);
+        if let Block::Paragraph(spans) = &blocks[0] {
+            let text: String = spans.iter().map(|s| s.text.clone()).collect();
+            assert_eq!(
+                text,
+                "She counted - one, two 1. three # four * five and six."
+            );
+            assert!(spans.iter().any(|s| s.italic && s.text == "six"));
+        } else {
+            panic!("Expected a paragraph");
+        }
+    }
+
+    #[test]
+    fn test_hard_line_breaks() {
+        let content = "Roses are red,  \nviolets are blue.\\\nThe end.";
+        let blocks = content_to_blocks(content.to_string());
+        assert_eq!(blocks.len(), 1);
+        if let Block::Paragraph(spans) = &blocks[0] {
+            let breaks = spans.iter().filter(|s| s.line_break).count();
+            assert_eq!(breaks, 2);
+        } else {
+            panic!("Expected a paragraph");
+        }
+    }
+
+    #[test]
+    fn test_blockquote_paragraphs() {
+        let content = "Before.\n> One\n> still one\n>\n> Two\n\nAfter.";
+        let blocks = content_to_blocks(content.to_string());
+        assert_eq!(blocks.len(), 3);
+        if let Block::BlockQuote(paragraphs) = &blocks[1] {
+            assert_eq!(paragraphs.len(), 2);
+        } else {
+            panic!("Expected a blockquote");
+        }
+    }
+
+    #[test]
+    fn test_scene_breaks() {
+        let document = parse_markdown(slurp("examples/scene_breaks/scene_breaks.md")).unwrap();
+        let blocks = content_to_blocks(document.content);
+
+        // Each form of scene break separates two paragraphs, so the blocks alternate
+        assert_eq!(blocks.len(), 15);
+        for (i, block) in blocks.iter().enumerate() {
+            if i % 2 == 0 {
+                assert!(matches!(block, Block::Paragraph(_)), "block {i}: {block:?}");
+            } else {
+                assert_eq!(*block, Block::SceneBreak, "block {i}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_chapter_heading() {
+        assert_eq!(chapter_heading("Chapter {n}", 3, None), "Chapter 3");
+        assert_eq!(
+            chapter_heading("Chapter {n:words}", 3, Some("The Flood".to_string())),
+            "Chapter Three: The Flood"
+        );
+        assert_eq!(
+            chapter_heading("{n:roman}. {title}", 3, Some("The Flood".to_string())),
+            "III. The Flood"
+        );
+        assert_eq!(chapter_heading("{n:roman}. {title}", 3, None), "III");
+    }
+
     #[test]
     fn test_trim_links() {
         let s = "This is a test. [This is a link](https://example.com). [[This is only a test]].\nIf this were an actual emergency, you would be instructed where to go and what to do.";
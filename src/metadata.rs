@@ -1,10 +1,15 @@
 use serde::Deserialize;
 
-#[derive(Clone, Deserialize, Debug)]
+#[derive(Clone, Default, Deserialize, Debug)]
 pub struct Metadata {
     pub content_warnings: Option<Vec<String>>,
+    /// The files that make up the story, in order. An entry can also be a folder, or a glob
+    /// pattern like `Act 1/**/*.md`.
     pub include: Option<Vec<String>>,
 
+    /// Files, folders or glob patterns to leave out of `include`, like research or notes
+    pub exclude: Option<Vec<String>>,
+
     /// The shortened title of the story, used in the manuscript header.
     pub short_title: Option<String>,
 
@@ -13,18 +18,70 @@ pub struct Metadata {
     pub title: Option<String>,
     pub author: Option<String>,
 
+    /// The name the story is written under, overriding the pen name in your PII
+    pub pen_name: Option<String>,
+
+    /// Whose contact information goes on the first page: `author`, `agent` or `both`
+    pub contact: Option<String>,
+
     /// The heading to use when rendering the child documents
     pub heading: Option<String>,
+
+    /// A template for numbering chapters, i.e., "Chapter {n}", set on the story or a part.
+    /// `{n:words}` spells the number out, `{n:roman}` uses Roman numerals, and `{title}` is the
+    /// chapter's title.
+    pub chapter_heading: Option<String>,
+
+    /// Whether the file starts a new chapter
+    pub chapter: Option<bool>,
+
+    /// The chapter's title, set on its first file or its folder's metadata
+    pub chapter_title: Option<String>,
+
+    /// The marker used to separate scenes, overriding the configuration file
+    pub scene_break: Option<String>,
+
+    /// The kind of story, i.e., `novel`, `novella`, `short` or `flash`, which decides how the
+    /// first page is laid out
+    #[serde(rename = "type")]
+    pub story_type: Option<String>,
+
+    /// What the file is, if it's front or back matter rather than a scene: `epigraph`,
+    /// `dedication`, `afterword` or `author_bio`
+    pub role: Option<String>,
+
+    /// Who an epigraph is quoting, and where from
+    pub attribution: Option<String>,
+
+    /// Whether the front or back matter identifies the author, so it's left out of anonymous
+    /// manuscripts. An `author_bio` always does.
+    pub identifying: Option<bool>,
+
+    /// The number of words the author is aiming for, in the story, folder or scene
+    pub word_goal: Option<usize>,
 }
 
 impl Metadata {
+    /// Whether the file has no metadata worth speaking of. A `type` alone doesn't count, since
+    /// scenes are often tagged with `type: scene`.
     pub fn is_empty(&self) -> bool {
         self.content_warnings.is_none()
             && self.include.is_none()
+            && self.exclude.is_none()
             && self.short_title.is_none()
             && self.short_author.is_none()
             && self.title.is_none()
             && self.author.is_none()
+            && self.pen_name.is_none()
+            && self.contact.is_none()
             && self.heading.is_none()
+            && self.chapter_heading.is_none()
+            && self.chapter.is_none()
+            && self.chapter_title.is_none()
+            && self.scene_break.is_none()
+            && self.role.is_none()
+            && self.attribution.is_none()
+            && self.identifying.is_none()
+            && self.word_goal.is_none()
     }
 }
@@ -9,6 +9,8 @@ use std::path::{Path, PathBuf};
 
 use rand::distr::{Alphanumeric, SampleString};
 
+use crate::config::Config;
+
 const COMMANDS: &[&str] = &[
     "Export to Standard Manuscript Format (Classic)",
     "Export to Standard Manuscript Format (Modern)",
@@ -73,9 +75,9 @@ impl ObsidianShellcommands {
         command.icon = "lucide-book".to_string();
 
         // For checking the word count, we don't need to output the manuscript or worry about PII. We just need to
-        // know the path to the draft inside the vault.
+        // know the file that's open; md2ms walks up from it to find the story.
         let platform_specific_commands = PlatformSpecificCommands {
-            default: "md2ms compile --word-count {{folder_path:absolute}}".to_string(),
+            default: "md2ms compile --word-count {{file_path:absolute}}".to_string(),
         };
 
         command.platform_specific_commands = platform_specific_commands;
@@ -107,13 +109,20 @@ impl ObsidianShellcommands {
         pii.push(self.vault_folder.clone());
         pii.push("PII.md");
 
+        // Anything set in the configuration file is left out of the command, so that changing the
+        // configuration takes effect without reinstalling the integration.
+        let config = Config::load().unwrap_or_default();
+
+        let mut cmd = "md2ms compile {{folder_path:absolute}}".to_string();
+        if config.output_dir.is_none() {
+            cmd.push_str(&format!(" --output-dir \"{}\"", self.export_path.display()));
+        }
         // TODO: need to figure out how to make the path to PII more generic
         // I've added Obsidian as an argument, but I'll need to extract it from the vault_path
-        platform_specific_commands.default = format!(
-            "md2ms compile {{folder_path:absolute}} --output-dir \"{}\" --pii \"{}\"",
-            self.export_path.display(),
-            pii.display()
-        );
+        if config.pii.is_none() {
+            cmd.push_str(&format!(" --pii \"{}\"", pii.display()));
+        }
+        platform_specific_commands.default = cmd;
 
         command.platform_specific_commands = platform_specific_commands;
 
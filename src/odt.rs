@@ -0,0 +1,503 @@
+// Writes a manuscript as an OpenDocument Text (.odt) file
+//
+// An .odt is a zip archive of XML documents. We only need a handful of them: the content, the
+// styles (page layout and running header), the document metadata, and the manifest.
+// https://docs.oasis-open.org/office/OpenDocument/v1.3/
+use std::io::{Seek, Write};
+
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::context::Context;
+use crate::error::Md2msError;
+use crate::manuscript::{Element, Manuscript, Span};
+use crate::utils::{escape_xml, separate_thousands};
+
+const MIMETYPE: &str = "application/vnd.oasis.opendocument.text";
+
+const MANIFEST: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<manifest:manifest xmlns:manifest="urn:oasis:names:tc:opendocument:xmlns:manifest:1.0" manifest:version="1.2">
+ <manifest:file-entry manifest:full-path="/" manifest:version="1.2" manifest:media-type="application/vnd.oasis.opendocument.text"/>
+ <manifest:file-entry manifest:full-path="content.xml" manifest:media-type="text/xml"/>
+ <manifest:file-entry manifest:full-path="styles.xml" manifest:media-type="text/xml"/>
+ <manifest:file-entry manifest:full-path="meta.xml" manifest:media-type="text/xml"/>
+</manifest:manifest>
+"#;
+
+const NAMESPACES: &str = r#"xmlns:office="urn:oasis:names:tc:opendocument:xmlns:office:1.0" xmlns:style="urn:oasis:names:tc:opendocument:xmlns:style:1.0" xmlns:text="urn:oasis:names:tc:opendocument:xmlns:text:1.0" xmlns:table="urn:oasis:names:tc:opendocument:xmlns:table:1.0" xmlns:fo="urn:oasis:names:tc:opendocument:xmlns:xsl-fo-compatible:1.0" xmlns:svg="urn:oasis:names:tc:opendocument:xmlns:svg-compatible:1.0" xmlns:meta="urn:oasis:names:tc:opendocument:xmlns:meta:1.0" xmlns:dc="http://purl.org/dc/elements/1.1/""#;
+
+/// Render a list of spans into (possibly nested) text:span elements
+fn spans_to_xml(spans: &[Span]) -> String {
+    let mut xml = String::new();
+    for span in spans {
+        let mut text = escape_xml(&span.text);
+        if span.italic {
+            text = format!(r#"<text:span text:style-name="Emphasis">{text}</text:span>"#);
+        }
+        if span.bold {
+            text = format!(r#"<text:span text:style-name="Strong_20_Emphasis">{text}</text:span>"#);
+        }
+        if span.strike {
+            text = format!(r#"<text:span text:style-name="Strikethrough">{text}</text:span>"#);
+        }
+        if span.underline {
+            text = format!(r#"<text:span text:style-name="Underline">{text}</text:span>"#);
+        }
+        xml.push_str(&text);
+        if span.line_break {
+            xml.push_str("<text:line-break/>");
+        }
+    }
+    xml
+}
+
+/// A single paragraph with the given style
+fn paragraph(style: &str, content: &str) -> String {
+    format!(r#"<text:p text:style-name="{style}">{content}</text:p>"#)
+}
+
+/// Render the body of the manuscript into ODF paragraphs
+fn body_xml(manuscript: &Manuscript) -> String {
+    let mut xml = String::new();
+    let scene_break = paragraph("Centered", &escape_xml(&manuscript.scene_break));
+
+    // Whether we're at the start of the story or a chapter, where a scene break would be out of place
+    let mut first = true;
+    // The story opens on the page after the cover, which is page one
+    let mut cover = manuscript.story_type.cover_page();
+    for element in manuscript.elements() {
+        match element {
+            Element::Heading(heading) => {
+                let style = if cover { "Opening" } else { "Heading" };
+                xml.push_str(&paragraph(style, &escape_xml(heading)));
+                cover = false;
+                first = true;
+                continue;
+            }
+            Element::PageBreak => {
+                // An empty paragraph in the heading's style, so what follows starts where a
+                // chapter heading would
+                let style = if cover { "Opening" } else { "Heading" };
+                xml.push_str(&paragraph(style, ""));
+                cover = false;
+                first = true;
+                continue;
+            }
+            Element::SceneBreak => xml.push_str(&scene_break),
+            Element::Paragraph(spans) => xml.push_str(&paragraph("Body", &spans_to_xml(spans))),
+            Element::BlockQuote(quote) => {
+                // A block quote is set off with a scene break before and after the text
+                if !first {
+                    xml.push_str(&scene_break);
+                }
+                for spans in quote {
+                    xml.push_str(&paragraph("Quote", &spans_to_xml(spans)));
+                }
+                xml.push_str(&scene_break);
+            }
+            Element::Epigraph(spans) => xml.push_str(&paragraph("Epigraph", &spans_to_xml(spans))),
+            Element::Attribution(spans) => {
+                xml.push_str(&paragraph("Attribution", &spans_to_xml(spans)))
+            }
+            Element::Centered(spans) => xml.push_str(&paragraph("Centered", &spans_to_xml(spans))),
+            Element::End => xml.push_str(&paragraph("Centered", "END")),
+        }
+        first = false;
+    }
+    xml
+}
+
+fn styles_xml(ctx: &Context, manuscript: &Manuscript) -> String {
+    let font = escape_xml(&ctx.font);
+    // The font size in the context is in half-points, to match docx
+    let size = ctx.font_size / 2;
+    let header = escape_xml(&manuscript.running_header);
+    let (width, height) = ctx.paper.inches();
+
+    // Chapter headings start a third of the way down the page. The contact block has a fixed line
+    // height, so the title can start there too, or halfway down a cover page, whatever the font.
+    let opening = ctx.paper.opening_twips() as f32 / 1440.0;
+    let top = if manuscript.story_type.cover_page() {
+        ctx.paper.cover_twips() as f32 / 1440.0
+    } else {
+        opening
+    };
+    let line = size as f32 * 1.2;
+    let contact_lines = manuscript.title_page.contact.len().max(1) as f32;
+    let title = (top - contact_lines * line / 72.0).max(0.0);
+
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<office:document-styles {NAMESPACES} office:version="1.2">
+ <office:font-face-decls>
+  <style:font-face style:name="{font}" svg:font-family="'{font}'"/>
+ </office:font-face-decls>
+ <office:styles>
+  <style:default-style style:family="paragraph">
+   <style:paragraph-properties fo:margin-top="0in" fo:margin-bottom="0in"/>
+   <style:text-properties style:font-name="{font}" fo:font-size="{size}pt"/>
+  </style:default-style>
+  <style:style style:name="Standard" style:family="paragraph"/>
+  <style:style style:name="Body" style:family="paragraph" style:parent-style-name="Standard">
+   <style:paragraph-properties fo:line-height="200%" fo:text-indent="0.63cm"/>
+  </style:style>
+  <style:style style:name="Quote" style:family="paragraph" style:parent-style-name="Standard">
+   <style:paragraph-properties fo:line-height="200%" fo:margin-left="0.63cm" fo:text-indent="0.63cm"/>
+  </style:style>
+  <style:style style:name="Centered" style:family="paragraph" style:parent-style-name="Standard">
+   <style:paragraph-properties fo:text-align="center" fo:line-height="200%"/>
+  </style:style>
+  <style:style style:name="Epigraph" style:family="paragraph" style:parent-style-name="Standard">
+   <style:paragraph-properties fo:line-height="200%" fo:margin-left="0.5in" fo:margin-right="0.5in"/>
+  </style:style>
+  <style:style style:name="Attribution" style:family="paragraph" style:parent-style-name="Standard">
+   <style:paragraph-properties fo:text-align="end" fo:line-height="200%"/>
+  </style:style>
+  <style:style style:name="Contact" style:family="paragraph" style:parent-style-name="Standard">
+   <style:paragraph-properties fo:line-height="{line:.1}pt"/>
+  </style:style>
+  <style:style style:name="Title" style:family="paragraph" style:parent-style-name="Centered">
+   <style:paragraph-properties fo:margin-top="{title:.2}in"/>
+  </style:style>
+  <style:style style:name="Heading" style:family="paragraph" style:parent-style-name="Centered">
+   <style:paragraph-properties fo:break-before="page" fo:margin-top="{opening:.2}in"/>
+  </style:style>
+  <style:style style:name="Right" style:family="paragraph" style:parent-style-name="Contact">
+   <style:paragraph-properties fo:text-align="end"/>
+  </style:style>
+  <style:style style:name="Header" style:family="paragraph" style:parent-style-name="Right"/>
+  <style:style style:name="Emphasis" style:family="text">
+   <style:text-properties fo:font-style="italic"/>
+  </style:style>
+  <style:style style:name="Strong_20_Emphasis" style:display-name="Strong Emphasis" style:family="text">
+   <style:text-properties fo:font-weight="bold"/>
+  </style:style>
+  <style:style style:name="Strikethrough" style:family="text">
+   <style:text-properties style:text-line-through-style="solid"/>
+  </style:style>
+  <style:style style:name="Underline" style:family="text">
+   <style:text-properties style:text-underline-style="solid" style:text-underline-width="auto" style:text-underline-color="font-color"/>
+  </style:style>
+ </office:styles>
+ <office:automatic-styles>
+  <style:page-layout style:name="pm1">
+   <style:page-layout-properties fo:page-width="{width:.2}in" fo:page-height="{height:.2}in" fo:margin-top="0.5in" fo:margin-bottom="1in" fo:margin-left="1in" fo:margin-right="1in"/>
+   <style:header-style>
+    <style:header-footer-properties fo:min-height="0.25in" fo:margin-bottom="0.25in"/>
+   </style:header-style>
+  </style:page-layout>
+  <style:page-layout style:name="pm2">
+   <style:page-layout-properties fo:page-width="{width:.2}in" fo:page-height="{height:.2}in" fo:margin-top="1in" fo:margin-bottom="1in" fo:margin-left="1in" fo:margin-right="1in"/>
+  </style:page-layout>
+ </office:automatic-styles>
+ <office:master-styles>
+  <style:master-page style:name="Standard" style:page-layout-name="pm1">
+   <style:header>
+    <text:p text:style-name="Header">{header}<text:page-number text:select-page="current">1</text:page-number></text:p>
+   </style:header>
+  </style:master-page>
+  <style:master-page style:name="First_20_Page" style:display-name="First Page" style:page-layout-name="pm2" style:next-style-name="Standard"/>
+ </office:master-styles>
+</office:document-styles>
+"#
+    )
+}
+
+fn meta_xml(manuscript: &Manuscript) -> String {
+    let title_page = &manuscript.title_page;
+    let word_count = title_page.word_count;
+
+    let mut meta = format!("<dc:title>{}</dc:title>", escape_xml(&title_page.title));
+    if let Some(author) = &title_page.author {
+        let author = escape_xml(author);
+        meta.push_str(&format!(
+            "<meta:initial-creator>{author}</meta:initial-creator><dc:creator>{author}</dc:creator>"
+        ));
+    }
+
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<office:document-meta {NAMESPACES} office:version="1.2">
+ <office:meta><meta:generator>md2ms</meta:generator>{meta}<meta:document-statistic meta:word-count="{word_count}"/></office:meta>
+</office:document-meta>
+"#
+    )
+}
+
+fn content_xml(ctx: &Context, manuscript: &Manuscript) -> String {
+    let title_page = &manuscript.title_page;
+
+    // The contact block, in the top-left of the first page
+    let mut contact = String::new();
+    for line in &title_page.contact {
+        contact.push_str(&paragraph("Contact", &escape_xml(line)));
+    }
+    if contact.is_empty() {
+        contact = paragraph("Contact", "");
+    }
+
+    let about = paragraph(
+        "Right",
+        &format!(
+            "about {} words",
+            separate_thousands(title_page.word_count, &ctx.locale)
+        ),
+    );
+
+    let mut front = paragraph("Title", &escape_xml(&title_page.title));
+    if let Some(author) = &title_page.author {
+        front.push_str(&paragraph(
+            "Centered",
+            &format!("by {}", escape_xml(author)),
+        ));
+    }
+    if !title_page.content_warnings.is_empty() {
+        front.push_str(&paragraph(
+            "Centered",
+            &format!(
+                "CW: {}",
+                escape_xml(&title_page.content_warnings.join(", "))
+            ),
+        ));
+    }
+    front.push_str(&paragraph("Centered", ""));
+
+    let body = body_xml(manuscript);
+
+    // The title block spans the width of the page, inside the one inch margins
+    let width = ctx.paper.inches().0 - 2.0;
+    let column = width / 2.0;
+
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<office:document-content {NAMESPACES} office:version="1.2">
+ <office:automatic-styles>
+  <style:style style:name="TitleBlock" style:family="table" style:master-page-name="First_20_Page">
+   <style:table-properties style:width="{width:.2}in" table:align="margins"/>
+  </style:style>
+  <style:style style:name="TitleBlock.A" style:family="table-column">
+   <style:table-column-properties style:column-width="{column:.2}in"/>
+  </style:style>
+  <style:style style:name="Opening" style:family="paragraph" style:parent-style-name="Heading" style:master-page-name="Standard">
+   <style:paragraph-properties style:page-number="1"/>
+  </style:style>
+ </office:automatic-styles>
+ <office:body>
+  <office:text>
+   <table:table table:name="TitleBlock" table:style-name="TitleBlock">
+    <table:table-column table:style-name="TitleBlock.A" table:number-columns-repeated="2"/>
+    <table:table-row>
+     <table:table-cell office:value-type="string">{contact}</table:table-cell>
+     <table:table-cell office:value-type="string">{about}</table:table-cell>
+    </table:table-row>
+   </table:table>
+   {front}{body}
+  </office:text>
+ </office:body>
+</office:document-content>
+"#
+    )
+}
+
+/// Write the manuscript as an OpenDocument Text file
+pub fn write_odt<W: Write + Seek>(
+    ctx: &Context,
+    manuscript: &Manuscript,
+    writer: W,
+) -> Result<(), Md2msError> {
+    let mut zip = ZipWriter::new(writer);
+
+    // The mimetype must be the first entry in the archive, and it must not be compressed.
+    let stored = FileOptions::default().compression_method(CompressionMethod::Stored);
+    let deflated = FileOptions::default().compression_method(CompressionMethod::Deflated);
+
+    let entries = [
+        ("mimetype", MIMETYPE.to_string(), stored),
+        ("META-INF/manifest.xml", MANIFEST.to_string(), deflated),
+        ("content.xml", content_xml(ctx, manuscript), deflated),
+        ("styles.xml", styles_xml(ctx, manuscript), deflated),
+        ("meta.xml", meta_xml(manuscript), deflated),
+    ];
+
+    for (name, content, options) in entries {
+        zip.start_file(name, options)
+            .map_err(|_| Md2msError::PackError)?;
+        zip.write_all(content.as_bytes())
+            .map_err(|_| Md2msError::PackError)?;
+    }
+
+    match zip.finish() {
+        Ok(_) => Ok(()),
+        Err(_) => Err(Md2msError::PackError),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::manuscript::{Block, Chapter, Part, Scene, TitlePage};
+    use crate::{CompileArgs, PaperSize, StoryType};
+    use clap::Parser;
+
+    #[test]
+    fn test_body_xml() {
+        let manuscript = Manuscript {
+            scene_break: "* * *".to_string(),
+            parts: vec![Part {
+                chapters: vec![Chapter {
+                    heading: Some("Chapter 1".to_string()),
+                    scenes: vec![
+                        Scene {
+                            source: "scene1.md".to_string(),
+                            word_count: 2,
+                            blocks: vec![Block::Paragraph(vec![
+                                Span {
+                                    text: "Hello ".to_string(),
+                                    ..Default::default()
+                                },
+                                Span {
+                                    text: "world".to_string(),
+                                    italic: true,
+                                    ..Default::default()
+                                },
+                            ])],
+                            ..Default::default()
+                        },
+                        Scene {
+                            source: "scene2.md".to_string(),
+                            word_count: 1,
+                            blocks: vec![Block::BlockQuote(vec![vec![Span {
+                                text: "Quoted".to_string(),
+                                ..Default::default()
+                            }]])],
+                            ..Default::default()
+                        },
+                    ],
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let xml = body_xml(&manuscript);
+        assert_eq!(
+            xml,
+            concat!(
+                r#"<text:p text:style-name="Heading">Chapter 1</text:p>"#,
+                r#"<text:p text:style-name="Body">Hello <text:span text:style-name="Emphasis">world</text:span></text:p>"#,
+                r#"<text:p text:style-name="Centered">* * *</text:p>"#,
+                r#"<text:p text:style-name="Centered">* * *</text:p>"#,
+                r#"<text:p text:style-name="Quote">Quoted</text:p>"#,
+                r#"<text:p text:style-name="Centered">* * *</text:p>"#,
+                r#"<text:p text:style-name="Centered">END</text:p>"#
+            )
+        );
+    }
+
+    #[test]
+    fn test_block_quote_opens_chapter() {
+        let manuscript = Manuscript {
+            scene_break: "#".to_string(),
+            parts: vec![Part {
+                chapters: vec![Chapter {
+                    heading: Some("Chapter 1".to_string()),
+                    scenes: vec![Scene {
+                        blocks: vec![Block::BlockQuote(vec![vec![Span {
+                            text: "Epigraph".to_string(),
+                            ..Default::default()
+                        }]])],
+                        ..Default::default()
+                    }],
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        assert_eq!(
+            body_xml(&manuscript),
+            concat!(
+                r#"<text:p text:style-name="Heading">Chapter 1</text:p>"#,
+                r#"<text:p text:style-name="Quote">Epigraph</text:p>"#,
+                r#"<text:p text:style-name="Centered">#</text:p>"#,
+                r#"<text:p text:style-name="Centered">END</text:p>"#
+            )
+        );
+    }
+
+    #[test]
+    fn test_chapter_opening() {
+        let manuscript = Manuscript {
+            title_page: TitlePage {
+                contact: vec!["Name".to_string(), "Email".to_string()],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        // A third of the way down the text area of the page
+        let mut ctx = Context::with_config(
+            &CompileArgs::parse_from(["compile", "examples/short"]),
+            Config::default(),
+        );
+        assert!(styles_xml(&ctx, &manuscript)
+            .contains(r#"fo:break-before="page" fo:margin-top="3.00in""#));
+        ctx.paper = PaperSize::A4;
+        assert!(styles_xml(&ctx, &manuscript)
+            .contains(r#"fo:break-before="page" fo:margin-top="3.23in""#));
+
+        // The title lines up with it, below the two lines of the contact block
+        ctx.paper = PaperSize::Letter;
+        let xml = styles_xml(&ctx, &manuscript);
+        assert!(xml.contains(r#"<style:paragraph-properties fo:line-height="14.4pt"/>"#));
+        assert!(xml.contains(r#"<style:paragraph-properties fo:margin-top="2.60in"/>"#));
+    }
+
+    #[test]
+    fn test_cover_page() {
+        let mut manuscript = Manuscript {
+            title_page: TitlePage {
+                contact: vec!["Name".to_string(), "Email".to_string()],
+                ..Default::default()
+            },
+            story_type: StoryType::from_name("Novel"),
+            parts: vec![Part {
+                chapters: vec![Chapter {
+                    scenes: vec![Scene {
+                        blocks: vec![Block::Paragraph(vec![Span {
+                            text: "It begins.".to_string(),
+                            ..Default::default()
+                        }])],
+                        ..Default::default()
+                    }],
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+
+        // A novel without a heading still starts on page one, after the cover
+        assert_eq!(
+            body_xml(&manuscript),
+            concat!(
+                r#"<text:p text:style-name="Opening"></text:p>"#,
+                r#"<text:p text:style-name="Body">It begins.</text:p>"#,
+                r#"<text:p text:style-name="Centered">END</text:p>"#
+            )
+        );
+
+        // The title is halfway down the cover, rather than a third of the way down the first page
+        let ctx = Context::with_config(
+            &CompileArgs::parse_from(["compile", "examples/short"]),
+            Config::default(),
+        );
+        assert!(styles_xml(&ctx, &manuscript)
+            .contains(r#"<style:paragraph-properties fo:margin-top="4.10in"/>"#));
+
+        manuscript.story_type = StoryType::from_name("story");
+        assert_eq!(manuscript.story_type, StoryType::Short);
+        assert!(body_xml(&manuscript).starts_with(r#"<text:p text:style-name="Body">"#));
+    }
+}
@@ -0,0 +1,625 @@
+// Writes a manuscript directly to PDF
+//
+// This is a deliberately small PDF writer: it only uses the standard Type 1 fonts that every PDF
+// reader ships with (Courier and Times), so there's nothing to embed and no external tools are
+// needed to produce a submission-ready PDF on a headless machine.
+// https://opensource.adobe.com/dc-acrobat-sdk-docs/pdfstandards/PDF32000_2008.pdf
+use std::io::Write;
+
+use crate::context::Context;
+use crate::error::Md2msError;
+use crate::manuscript::{Element, Manuscript, Span};
+use crate::utils::separate_thousands;
+
+/// One inch, in points
+const INCH: f32 = 72.0;
+
+/// The first-line indent, matching the docx manuscript (357 dxa)
+const INDENT: f32 = 357.0 / 20.0;
+
+/// Glyph widths for Times-Roman, in 1/1000 em, for the printable ASCII range (0x20..=0x7E).
+/// Taken from the Adobe Font Metrics for the standard 14 fonts.
+#[rustfmt::skip]
+const TIMES_WIDTHS: [u16; 95] = [
+    // space ! " # $ % & ' ( ) * + , - . /
+    250, 333, 408, 500, 500, 833, 778, 180, 333, 333, 500, 564, 250, 333, 250, 278,
+    // 0 - 9
+    500, 500, 500, 500, 500, 500, 500, 500, 500, 500,
+    // : ; < = > ? @
+    278, 278, 564, 564, 564, 444, 921,
+    // A - Z
+    722, 667, 667, 722, 611, 556, 722, 722, 333, 389, 722, 611, 889,
+    722, 722, 556, 722, 667, 556, 611, 722, 722, 944, 722, 722, 611,
+    // [ \ ] ^ _ `
+    333, 278, 333, 469, 500, 333,
+    // a - z
+    444, 500, 444, 500, 444, 333, 500, 500, 278, 278, 500, 278, 778,
+    500, 500, 500, 500, 333, 389, 278, 500, 500, 722, 500, 500, 444,
+    // { | } ~
+    480, 200, 480, 541,
+];
+
+/// The two font families a manuscript can be set in
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum FontFamily {
+    Courier,
+    Times,
+}
+
+impl FontFamily {
+    /// Map the font requested for the manuscript to the closest standard PDF font, warning when
+    /// there isn't one and it falls back to Times
+    fn from_name(name: &str, ctx: &mut Context) -> Self {
+        let lowercase = name.to_lowercase();
+        if lowercase.contains("courier") {
+            FontFamily::Courier
+        } else {
+            if !lowercase.contains("times") {
+                ctx.warn(format!(
+                    "{name} isn't one of the standard PDF fonts, so the PDF is set in Times"
+                ));
+            }
+            FontFamily::Times
+        }
+    }
+
+    /// The PostScript names of the regular, italic, bold and bold italic faces
+    fn faces(&self) -> [&'static str; 4] {
+        match self {
+            FontFamily::Courier => [
+                "Courier",
+                "Courier-Oblique",
+                "Courier-Bold",
+                "Courier-BoldOblique",
+            ],
+            FontFamily::Times => [
+                "Times-Roman",
+                "Times-Italic",
+                "Times-Bold",
+                "Times-BoldItalic",
+            ],
+        }
+    }
+
+    /// The width of a character, in 1/1000 em.
+    ///
+    /// The Times widths are those of the roman face; the italic and bold faces are close enough
+    /// for the purposes of wrapping a line.
+    fn char_width(&self, c: char) -> f32 {
+        match self {
+            FontFamily::Courier => 600.0,
+            FontFamily::Times => match c {
+                ' '..='~' => TIMES_WIDTHS[c as usize - 0x20] as f32,
+                '—' | '…' => 1000.0,
+                '‘' | '’' => 333.0,
+                '“' | '”' => 444.0,
+                '•' => 350.0,
+                _ => 500.0,
+            },
+        }
+    }
+}
+
+/// Encode a character in WinAnsiEncoding, which is what the standard fonts use.
+fn win_ansi(c: char) -> u8 {
+    match c {
+        ' '..='~' => c as u8,
+        '\u{a0}'..='\u{ff}' => c as u32 as u8,
+        '€' => 0x80,
+        '…' => 0x85,
+        '‘' => 0x91,
+        '’' => 0x92,
+        '“' => 0x93,
+        '”' => 0x94,
+        '•' => 0x95,
+        '–' => 0x96,
+        '—' => 0x97,
+        _ => b'?',
+    }
+}
+
+/// Encode text as a PDF literal string, escaping anything outside of printable ASCII.
+fn pdf_string(text: &str) -> String {
+    let mut s = String::from("(");
+    for c in text.chars() {
+        match win_ansi(c) {
+            b'(' => s.push_str("\\("),
+            b')' => s.push_str("\\)"),
+            b'\\' => s.push_str("\\\\"),
+            b @ 0x20..=0x7e => s.push(b as char),
+            b => s.push_str(&format!("\\{b:03o}")),
+        }
+    }
+    s.push(')');
+    s
+}
+
+/// How the lines of a paragraph are placed between the margins
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Align {
+    Left,
+    Center,
+    Right,
+}
+
+/// A run of text on a line, set in a single face
+struct Piece {
+    text: String,
+    face: usize,
+    strike: bool,
+    underline: bool,
+}
+
+/// Lays out the manuscript, page by page, into PDF content streams.
+struct Layout {
+    family: FontFamily,
+    size: f32,
+    width: f32,
+    height: f32,
+    header: String,
+    /// The number of pages before page one, like a novel's cover page
+    unnumbered: usize,
+    pages: Vec<String>,
+    /// The top of the next line, measured from the bottom of the page
+    y: f32,
+}
+
+impl Layout {
+    fn new(family: FontFamily, size: f32, width: f32, height: f32, header: String) -> Self {
+        let mut layout = Layout {
+            family,
+            size,
+            width,
+            height,
+            header,
+            unnumbered: 0,
+            pages: vec![],
+            y: 0.0,
+        };
+        layout.new_page();
+        layout
+    }
+
+    fn top(&self) -> f32 {
+        self.height - INCH
+    }
+
+    fn bottom(&self) -> f32 {
+        INCH
+    }
+
+    fn text_width(&self, text: &str) -> f32 {
+        text.chars().map(|c| self.family.char_width(c)).sum::<f32>() * self.size / 1000.0
+    }
+
+    /// Start a new page. Every page but the first gets the running header.
+    fn new_page(&mut self) {
+        let mut content = String::new();
+        if !self.pages.is_empty() {
+            let header = format!("{}{}", self.header, self.pages.len() + 1 - self.unnumbered);
+            let x = self.width - INCH - self.text_width(&header);
+            let y = self.height - INCH / 2.0 - self.size;
+            content.push_str(&self.text_op(&header, 0, x, y));
+        }
+        self.pages.push(content);
+        self.y = self.top();
+    }
+
+    /// Move down to a point on the page, measured as a fraction of the text area.
+    fn move_to_fraction(&mut self, fraction: f32) {
+        let y = self.top() - (self.top() - self.bottom()) * fraction;
+        if y < self.y {
+            self.y = y;
+        }
+    }
+
+    fn text_op(&self, text: &str, face: usize, x: f32, y: f32) -> String {
+        format!(
+            "BT /F{} {:.2} Tf 1 0 0 1 {:.2} {:.2} Tm {} Tj ET\n",
+            face + 1,
+            self.size,
+            x,
+            y,
+            pdf_string(text)
+        )
+    }
+
+    /// Place a line of pieces on the page, starting at x, breaking the page if needed.
+    fn place_line(&mut self, pieces: &[Piece], x: f32, leading: f32) {
+        if self.y - leading < self.bottom() {
+            self.new_page();
+        }
+        let baseline = self.y - self.size;
+
+        let mut ops = String::new();
+        let mut x = x;
+        for piece in pieces {
+            let width = self.text_width(&piece.text);
+            ops.push_str(&self.text_op(&piece.text, piece.face, x, baseline));
+            // Strike through the middle of the text, or underline just below the baseline
+            let rules = [
+                (piece.strike, self.size * 0.3),
+                (piece.underline, -self.size * 0.1),
+            ];
+            for (_, offset) in rules.iter().filter(|(rule, _)| *rule) {
+                let y = baseline + offset;
+                ops.push_str(&format!(
+                    "0.5 w {:.2} {:.2} m {:.2} {:.2} l S\n",
+                    x,
+                    y,
+                    x + self.text_width(piece.text.trim_end()),
+                    y
+                ));
+            }
+            x += width;
+        }
+        if let Some(page) = self.pages.last_mut() {
+            page.push_str(&ops);
+        }
+        self.y -= leading;
+    }
+
+    /// A single line of text, left or right aligned within the margins.
+    fn line(&mut self, text: &str, right: bool, leading: f32) {
+        let x = if right {
+            self.width - INCH - self.text_width(text)
+        } else {
+            INCH
+        };
+        self.place_line(
+            &[Piece {
+                text: text.to_string(),
+                face: 0,
+                strike: false,
+                underline: false,
+            }],
+            x,
+            leading,
+        );
+    }
+
+    /// A single line of text, centered between the margins.
+    fn centered(&mut self, text: &str, leading: f32) {
+        let x = (self.width - self.text_width(text)) / 2.0;
+        self.place_line(
+            &[Piece {
+                text: text.to_string(),
+                face: 0,
+                strike: false,
+                underline: false,
+            }],
+            x,
+            leading,
+        );
+    }
+
+    /// Place a wrapped line of a paragraph, aligned between `start` and the right margin less
+    /// `right`.
+    fn place_aligned(
+        &mut self,
+        pieces: &[Piece],
+        start: f32,
+        right: f32,
+        align: Align,
+        leading: f32,
+    ) {
+        let text: String = pieces.iter().map(|piece| piece.text.as_str()).collect();
+        let space = self.width - INCH - right - start - self.text_width(text.trim_end());
+        let x = match align {
+            Align::Left => start,
+            Align::Center => start + space / 2.0,
+            Align::Right => start + space,
+        };
+        self.place_line(pieces, x, leading);
+    }
+
+    /// Wrap a paragraph of spans to the text area, less the indents, and place it on the page.
+    fn paragraph(
+        &mut self,
+        spans: &[Span],
+        left: f32,
+        first_line: f32,
+        right_indent: f32,
+        align: Align,
+        leading: f32,
+    ) {
+        let right = self.width - INCH - right_indent;
+        let mut start = INCH + left + first_line;
+        let mut line: Vec<Piece> = vec![];
+        let mut line_width = 0.0;
+
+        for span in spans {
+            let face = (span.italic as usize) + (span.bold as usize) * 2;
+            for word in span.text.split_inclusive(' ') {
+                let width = self.text_width(word.trim_end());
+                if !line.is_empty() && start + line_width + width > right {
+                    self.place_aligned(&line, start, right_indent, align, leading);
+                    line.clear();
+                    line_width = 0.0;
+                    start = INCH + left;
+                }
+                if line.is_empty() && word.trim().is_empty() {
+                    // Don't start a line with a space
+                    continue;
+                }
+                line_width += self.text_width(word);
+                match line.last_mut() {
+                    Some(piece)
+                        if piece.face == face
+                            && piece.strike == span.strike
+                            && piece.underline == span.underline =>
+                    {
+                        piece.text.push_str(word)
+                    }
+                    _ => line.push(Piece {
+                        text: word.to_string(),
+                        face,
+                        strike: span.strike,
+                        underline: span.underline,
+                    }),
+                }
+            }
+            if span.line_break {
+                // A hard line break, even if the line is empty
+                self.place_aligned(&line, start, right_indent, align, leading);
+                line.clear();
+                line_width = 0.0;
+                start = INCH + left;
+            }
+        }
+        if !line.is_empty() {
+            self.place_aligned(&line, start, right_indent, align, leading);
+        }
+    }
+}
+
+/// Lay out the manuscript into one content stream per page
+fn layout(ctx: &mut Context, manuscript: &Manuscript) -> Layout {
+    let title_page = &manuscript.title_page;
+    let (width, height) = ctx.paper.points();
+    // The font size in the context is in half-points, to match docx
+    let size = ctx.font_size as f32 / 2.0;
+    let single = size;
+    let double = size * 2.0;
+
+    let font = ctx.font.clone();
+    let mut layout = Layout::new(
+        FontFamily::from_name(&font, ctx),
+        size,
+        width,
+        height,
+        manuscript.running_header.clone(),
+    );
+
+    // The word count sits in the top-right corner, on the same line as the start of the contact block
+    let about = format!(
+        "about {} words",
+        separate_thousands(title_page.word_count, &ctx.locale)
+    );
+    let top = layout.y;
+    layout.line(&about, true, single);
+    layout.y = top;
+    for line in &title_page.contact {
+        layout.line(line, false, single);
+    }
+
+    // The title should appear roughly a third of the way down the first page, or halfway down a
+    // cover page
+    let cover = manuscript.story_type.cover_page();
+    layout.move_to_fraction(if cover { 0.5 } else { 1.0 / 3.0 });
+    layout.centered(&title_page.title, double);
+    if let Some(author) = &title_page.author {
+        layout.centered(&format!("by {author}"), double);
+    }
+    if !title_page.content_warnings.is_empty() {
+        layout.centered(
+            &format!("CW: {}", title_page.content_warnings.join(", ")),
+            double,
+        );
+    }
+    layout.y -= double;
+    if cover {
+        layout.unnumbered = 1;
+    }
+
+    // Whether we're at the start of the story or a chapter, where a scene break would be out of place
+    let mut first = true;
+    for element in manuscript.elements() {
+        match element {
+            Element::Heading(heading) => {
+                // A chapter starts on a new page, a third of the way down
+                layout.new_page();
+                layout.move_to_fraction(1.0 / 3.0);
+                layout.centered(heading, double);
+                first = true;
+                continue;
+            }
+            Element::PageBreak => {
+                // Anything with a page of its own starts where a chapter heading would
+                layout.new_page();
+                layout.move_to_fraction(1.0 / 3.0);
+                layout.y -= double;
+                first = true;
+                continue;
+            }
+            Element::SceneBreak => layout.centered(&manuscript.scene_break, double),
+            Element::Paragraph(spans) => {
+                layout.paragraph(spans, 0.0, INDENT, 0.0, Align::Left, double)
+            }
+            Element::BlockQuote(quote) => {
+                // A block quote is set off with a scene break before and after the text
+                if !first {
+                    layout.centered(&manuscript.scene_break, double);
+                }
+                for spans in quote {
+                    layout.paragraph(spans, INDENT, INDENT, 0.0, Align::Left, double);
+                }
+                layout.centered(&manuscript.scene_break, double);
+            }
+            Element::Epigraph(spans) => {
+                layout.paragraph(spans, INCH / 2.0, 0.0, INCH / 2.0, Align::Left, double)
+            }
+            Element::Attribution(spans) => {
+                layout.paragraph(spans, 0.0, 0.0, 0.0, Align::Right, double)
+            }
+            Element::Centered(spans) => {
+                layout.paragraph(spans, 0.0, 0.0, 0.0, Align::Center, double)
+            }
+            Element::End => layout.centered("END", double),
+        }
+        first = false;
+    }
+
+    layout
+}
+
+/// Assemble the objects of the PDF, followed by the cross-reference table and trailer.
+fn build(manuscript: &Manuscript, layout: Layout) -> Vec<u8> {
+    let mut objects: Vec<String> = vec![];
+
+    // 1: catalog, 2: page tree, 3: document information, 4-7: fonts
+    objects.push("<< /Type /Catalog /Pages 2 0 R >>".to_string());
+
+    let first_page = 8;
+    let kids: Vec<String> = (0..layout.pages.len())
+        .map(|i| format!("{} 0 R", first_page + i * 2))
+        .collect();
+    objects.push(format!(
+        "<< /Type /Pages /Kids [{}] /Count {} >>",
+        kids.join(" "),
+        layout.pages.len()
+    ));
+
+    let mut info = format!(
+        "<< /Producer (md2ms) /Title {}",
+        pdf_string(&manuscript.title_page.title)
+    );
+    if let Some(author) = &manuscript.title_page.author {
+        info.push_str(&format!(" /Author {}", pdf_string(author)));
+    }
+    info.push_str(" >>");
+    objects.push(info);
+
+    for face in layout.family.faces() {
+        objects.push(format!(
+            "<< /Type /Font /Subtype /Type1 /BaseFont /{face} /Encoding /WinAnsiEncoding >>"
+        ));
+    }
+
+    for (i, content) in layout.pages.iter().enumerate() {
+        objects.push(format!(
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {:.2} {:.2}] \
+             /Resources << /Font << /F1 4 0 R /F2 5 0 R /F3 6 0 R /F4 7 0 R >> >> \
+             /Contents {} 0 R >>",
+            layout.width,
+            layout.height,
+            first_page + i * 2 + 1
+        ));
+        objects.push(format!(
+            "<< /Length {} >>\nstream\n{}endstream",
+            content.len(),
+            content
+        ));
+    }
+
+    let mut pdf = String::from("%PDF-1.4\n");
+    let mut offsets: Vec<usize> = vec![];
+    for (i, object) in objects.iter().enumerate() {
+        offsets.push(pdf.len());
+        pdf.push_str(&format!("{} 0 obj\n{}\nendobj\n", i + 1, object));
+    }
+
+    let xref = pdf.len();
+    pdf.push_str(&format!(
+        "xref\n0 {}\n0000000000 65535 f \n",
+        objects.len() + 1
+    ));
+    for offset in offsets {
+        pdf.push_str(&format!("{offset:010} 00000 n \n"));
+    }
+    pdf.push_str(&format!(
+        "trailer\n<< /Size {} /Root 1 0 R /Info 3 0 R >>\nstartxref\n{}\n%%EOF\n",
+        objects.len() + 1,
+        xref
+    ));
+    pdf.into_bytes()
+}
+
+/// Write the manuscript as a PDF
+pub fn write_pdf<W: Write>(
+    ctx: &mut Context,
+    manuscript: &Manuscript,
+    mut writer: W,
+) -> Result<(), Md2msError> {
+    let layout = layout(ctx, manuscript);
+    match writer.write_all(&build(manuscript, layout)) {
+        Ok(_) => Ok(()),
+        Err(_) => Err(Md2msError::PackError),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::CompileArgs;
+    use clap::Parser;
+
+    #[test]
+    fn test_pdf_string() {
+        assert_eq!(pdf_string("(a) \\ b"), "(\\(a\\) \\\\ b)");
+        assert_eq!(pdf_string("one—two"), "(one\\227two)");
+    }
+
+    #[test]
+    fn test_times_widths() {
+        assert_eq!(FontFamily::Times.char_width(' '), 250.0);
+        assert_eq!(FontFamily::Times.char_width('A'), 722.0);
+        assert_eq!(FontFamily::Times.char_width('~'), 541.0);
+        assert_eq!(FontFamily::Courier.char_width('W'), 600.0);
+    }
+
+    #[test]
+    fn test_font_family_from_name() {
+        let mut ctx = Context::with_config(
+            &CompileArgs::parse_from(["compile", "examples/short"]),
+            Config::default(),
+        );
+        assert_eq!(
+            FontFamily::from_name("Courier Prime", &mut ctx),
+            FontFamily::Courier
+        );
+        assert_eq!(
+            FontFamily::from_name("Times New Roman", &mut ctx),
+            FontFamily::Times
+        );
+        assert!(ctx.warnings.is_empty());
+
+        // Anything else falls back to Times, with a warning
+        assert_eq!(
+            FontFamily::from_name("Garamond", &mut ctx),
+            FontFamily::Times
+        );
+        assert_eq!(
+            ctx.warnings,
+            vec!["Garamond isn't one of the standard PDF fonts, so the PDF is set in Times"]
+        );
+    }
+
+    #[test]
+    fn test_paragraph_wraps_and_breaks_pages() {
+        let mut layout = Layout::new(FontFamily::Courier, 12.0, 612.0, 792.0, "Header / ".into());
+        // 65 characters fit on a 6.5" line of 12pt Courier, so each of these is a line of its own.
+        let spans: Vec<Span> = (0..60)
+            .map(|_| Span {
+                text: format!("{} ", "x".repeat(60)),
+                ..Default::default()
+            })
+            .collect();
+        layout.paragraph(&spans, 0.0, 0.0, 0.0, Align::Left, 24.0);
+        // 27 double-spaced lines fit on a page with one inch margins
+        assert_eq!(layout.pages.len(), 3);
+        assert!(layout.pages[1].contains("(Header / 2)"));
+        assert!(!layout.pages[0].contains("Header"));
+    }
+}
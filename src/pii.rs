@@ -1,10 +1,15 @@
 use serde::Deserialize;
 
+use crate::ContactBlock;
+
 #[derive(Clone, Deserialize, Debug)]
 pub struct PII {
     /// Your full legal name
     pub legal_name: Option<String>,
 
+    /// The name you write under, if it isn't your legal name
+    pub pen_name: Option<String>,
+
     /// Your email address
     pub email: Option<String>,
 
@@ -26,4 +31,136 @@ pub struct PII {
 
     /// A list of professional affiliations, if applicable.
     pub affiliations: Option<Vec<String>>,
+
+    /// Your agent, if you have one
+    pub agent: Option<Agent>,
+}
+
+/// An agent's contact information, which can take the place of the author's on the first page
+#[derive(Clone, Deserialize, Debug, Default)]
+pub struct Agent {
+    /// The agent's name
+    pub name: Option<String>,
+
+    /// The agency they work for
+    pub agency: Option<String>,
+
+    pub email: Option<String>,
+    pub phone: Option<String>,
+
+    /// The agency's physical address
+    pub address1: Option<String>,
+    pub address2: Option<String>,
+    pub city: Option<String>,
+    pub state: Option<String>,
+    pub postal_code: Option<String>,
+    pub country: Option<String>,
+}
+
+impl Agent {
+    /// The lines of the agent's contact block
+    pub fn contact_lines(&self) -> Vec<String> {
+        let mut lines: Vec<String> = Vec::new();
+        lines.extend(given(&self.name));
+        lines.extend(given(&self.agency));
+        lines.extend(address_lines(
+            &self.address1,
+            &self.address2,
+            &self.city,
+            &self.state,
+            &self.postal_code,
+            &self.country,
+        ));
+        lines.extend(given(&self.email));
+        lines.extend(given(&self.phone));
+        lines
+    }
+}
+
+/// The lines of a postal address. The city, state and postal code share a line, which is left
+/// out unless all three are given.
+fn address_lines(
+    address1: &Option<String>,
+    address2: &Option<String>,
+    city: &Option<String>,
+    state: &Option<String>,
+    postal_code: &Option<String>,
+    country: &Option<String>,
+) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    lines.extend(given(address1));
+    lines.extend(given(address2));
+    if let (Some(city), Some(state), Some(postal_code)) =
+        (given(city), given(state), given(postal_code))
+    {
+        lines.push(format!("{city}, {state}, {postal_code}"));
+    }
+    lines.extend(given(country));
+    lines
+}
+
+/// A field of the contact block, unless it's missing or blank
+fn given(field: &Option<String>) -> Option<String> {
+    field
+        .as_deref()
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+        .map(str::to_string)
+}
+
+impl PII {
+    /// The lines of the contact block, in the order they appear on the first page. If the story
+    /// is written under a pen name, the legal name says so.
+    pub fn contact_lines(&self, pen_name: Option<&str>) -> Vec<String> {
+        let mut lines: Vec<String> = Vec::new();
+
+        if let Some(legal_name) = given(&self.legal_name) {
+            match pen_name {
+                Some(pen_name) if pen_name != legal_name => {
+                    lines.push(format!("{legal_name} (writing as {pen_name})"))
+                }
+                _ => lines.push(legal_name),
+            }
+        }
+        lines.extend(address_lines(
+            &self.address1,
+            &self.address2,
+            &self.city,
+            &self.state,
+            &self.postal_code,
+            &self.country,
+        ));
+        lines.extend(given(&self.email));
+        lines.extend(given(&self.phone));
+        let affiliations: Vec<&str> = self
+            .affiliations
+            .iter()
+            .flatten()
+            .map(|affiliation| affiliation.trim())
+            .filter(|affiliation| !affiliation.is_empty())
+            .collect();
+        if !affiliations.is_empty() {
+            lines.push(format!("Active member: {}", affiliations.join(", ")));
+        }
+        lines
+    }
+
+    /// The contact block on the first page: the author's, the agent's, or the author's with the
+    /// agent's below it. Without an agent, it's always the author's.
+    pub fn contact_block(&self, block: ContactBlock, pen_name: Option<&str>) -> Vec<String> {
+        let Some(agent) = &self.agent else {
+            return self.contact_lines(pen_name);
+        };
+        match block {
+            ContactBlock::Author => self.contact_lines(pen_name),
+            ContactBlock::Agent => agent.contact_lines(),
+            ContactBlock::Both => {
+                let mut lines = self.contact_lines(pen_name);
+                lines.push(String::new());
+                lines.push("Represented by:".to_string());
+                lines.extend(agent.contact_lines());
+                lines
+            }
+        }
+    }
 }
@@ -0,0 +1,223 @@
+// Machine-readable reports of a compile, for `--format json`
+//
+// The schema is documented in the README. Bump REPORT_VERSION when a field is removed or changes
+// meaning; adding a field doesn't need a new version.
+use clap::ValueEnum;
+use serde::Serialize;
+
+use crate::manuscript::Manuscript;
+use crate::utils::round_up;
+use crate::word_count::WordCount;
+use crate::{DocumentFormat, Style};
+
+/// The version of the JSON report's schema
+pub const REPORT_VERSION: usize = 1;
+
+/// How to print the results of a compile or word count
+#[derive(Clone, Copy, Debug, Default, PartialEq, ValueEnum)]
+pub enum OutputFormat {
+    /// Prose, for people
+    #[default]
+    Text,
+    /// A JSON document, for scripts
+    Json,
+}
+
+/// Everything a compile or word count did
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct Report {
+    pub version: usize,
+    pub title: String,
+    pub word_count: WordCountReport,
+
+    /// The word count of each file the manuscript includes, in include order
+    pub files: Vec<FileReport>,
+
+    /// The scene the word count was asked for, if it was given a single file
+    pub scene: Option<FileReport>,
+
+    /// The manuscripts that were written, which is empty for a word count
+    pub written: Vec<WrittenFile>,
+
+    pub warnings: Vec<String>,
+
+    /// Problems that stopped the compile. The command exits with an error if there are any.
+    pub errors: Vec<String>,
+}
+
+/// The word count of the whole manuscript
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct WordCountReport {
+    pub exact: usize,
+
+    /// The figure on the title page, rounded up per Shunn
+    pub rounded: usize,
+    pub goal: Option<usize>,
+}
+
+/// The word count of a single file
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct FileReport {
+    /// The file, relative to the story
+    pub path: String,
+    pub words: usize,
+    pub goal: Option<usize>,
+}
+
+/// A manuscript that was written, and the variant it was built as
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct WrittenFile {
+    pub path: String,
+    pub format: DocumentFormat,
+    pub style: Style,
+    pub font: String,
+    pub anonymous: bool,
+}
+
+impl Report {
+    pub fn new() -> Self {
+        Report {
+            version: REPORT_VERSION,
+            ..Default::default()
+        }
+    }
+
+    /// Record the word counts of the manuscript, and of the scene that was asked for.
+    pub fn count(&mut self, manuscript: &Manuscript, scene: Option<&str>) {
+        let words = WordCount::new(manuscript);
+        self.title = words.label.clone();
+        self.word_count = WordCountReport {
+            exact: words.words,
+            rounded: round_up(words.words),
+            goal: words.goal,
+        };
+        self.files = words
+            .scenes()
+            .into_iter()
+            .map(|scene| FileReport {
+                path: scene.label.clone(),
+                words: scene.words,
+                goal: scene.goal,
+            })
+            .collect();
+        self.scene = scene.map(|scene| {
+            self.files
+                .iter()
+                .find(|f| f.path == scene)
+                .cloned()
+                .unwrap_or(FileReport {
+                    path: scene.to_string(),
+                    ..Default::default()
+                })
+        });
+    }
+
+    /// Add warnings, skipping any that have already been reported.
+    pub fn warn(&mut self, warnings: &[String]) {
+        for warning in warnings {
+            if !self.warnings.contains(warning) {
+                self.warnings.push(warning.clone());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manuscript::{Chapter, Part, Scene, TitlePage};
+
+    #[test]
+    fn test_report() {
+        let manuscript = Manuscript {
+            title_page: TitlePage {
+                title: "Story".to_string(),
+                ..Default::default()
+            },
+            word_goal: Some(1000),
+            parts: vec![Part {
+                chapters: vec![Chapter {
+                    scenes: vec![
+                        Scene {
+                            source: "one.md".to_string(),
+                            word_count: 120,
+                            word_goal: Some(100),
+                            ..Default::default()
+                        },
+                        Scene {
+                            source: "two.md".to_string(),
+                            word_count: 30,
+                            ..Default::default()
+                        },
+                    ],
+                    ..Default::default()
+                }],
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+
+        let mut report = Report::new();
+        report.count(&manuscript, Some("two.md"));
+        report.warn(&["Something odd".to_string()]);
+        report.warn(&["Something odd".to_string()]);
+
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "version": 1,
+                "title": "Story",
+                "word_count": {"exact": 150, "rounded": 200, "goal": 1000},
+                "files": [
+                    {"path": "one.md", "words": 120, "goal": 100},
+                    {"path": "two.md", "words": 30, "goal": null}
+                ],
+                "scene": {"path": "two.md", "words": 30, "goal": null},
+                "written": [],
+                "warnings": ["Something odd"],
+                "errors": []
+            })
+        );
+    }
+
+    #[test]
+    fn test_chapter_without_scenes() {
+        let manuscript = Manuscript {
+            parts: vec![Part {
+                chapters: vec![
+                    Chapter {
+                        scenes: vec![Scene {
+                            source: "one.md".to_string(),
+                            word_count: 120,
+                            ..Default::default()
+                        }],
+                        ..Default::default()
+                    },
+                    Chapter {
+                        heading: Some("Chapter 2".to_string()),
+                        ..Default::default()
+                    },
+                ],
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+
+        // The empty chapter isn't listed as a file
+        let mut report = Report::new();
+        report.count(&manuscript, None);
+        assert_eq!(
+            report.files,
+            vec![FileReport {
+                path: "one.md".to_string(),
+                words: 120,
+                goal: None,
+            }]
+        );
+
+        // Nor is the manuscript itself, when it has no scenes at all
+        report.count(&Manuscript::default(), None);
+        assert!(report.files.is_empty());
+    }
+}
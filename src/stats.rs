@@ -0,0 +1,334 @@
+// A history of the manuscript's word count, for `md2ms stats`
+//
+// Each `stats log` appends the current word count to a file next to the story, one JSON object per
+// line. `stats history` turns those snapshots into the words written each day and week.
+use chrono::{DateTime, Datelike, Duration, FixedOffset, Local, NaiveDate};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::error::Md2msError;
+use crate::utils::separate_thousands;
+use crate::word_count::WordCount;
+
+/// The name of the history file, kept in the story's folder
+pub const HISTORY_FILE: &str = ".md2ms-history.jsonl";
+
+/// The bars of a sparkline, from lowest to highest
+const SPARKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// The word count of the manuscript at a point in time
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct Entry {
+    /// When the count was logged, in the author's time zone at the time
+    pub timestamp: DateTime<FixedOffset>,
+    pub words: usize,
+
+    /// The word count of each file, relative to the story
+    pub files: BTreeMap<String, usize>,
+}
+
+impl Entry {
+    /// Take a snapshot of the word count, as of now.
+    pub fn new(words: &WordCount) -> Self {
+        Entry {
+            timestamp: Local::now().fixed_offset(),
+            words: words.words,
+            files: words
+                .scenes()
+                .into_iter()
+                .map(|scene| (scene.label.clone(), scene.words))
+                .collect(),
+        }
+    }
+
+    /// The day the count was logged on, as the author saw it
+    pub fn date(&self) -> NaiveDate {
+        self.timestamp.date_naive()
+    }
+}
+
+/// Every word count logged for a story
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct History {
+    pub entries: Vec<Entry>,
+}
+
+impl History {
+    /// The history file for a story, which is either a folder or a standalone Markdown file.
+    pub fn path(story: &Path) -> PathBuf {
+        if story.is_file() {
+            let name = story.file_stem().unwrap_or_default().to_string_lossy();
+            story.with_file_name(format!(".{name}{HISTORY_FILE}"))
+        } else {
+            story.join(HISTORY_FILE)
+        }
+    }
+
+    /// Parse a history, one entry per line.
+    pub fn parse(s: &str) -> Result<Self, Md2msError> {
+        let mut entries = vec![];
+        for line in s.lines().filter(|line| !line.trim().is_empty()) {
+            match serde_json::from_str::<Entry>(line) {
+                Ok(entry) => entries.push(entry),
+                Err(e) => return Err(Md2msError::HistoryError(e.to_string())),
+            }
+        }
+        entries.sort_by_key(|entry| entry.timestamp);
+        Ok(History { entries })
+    }
+
+    /// Read the history file, which is empty if nothing has been logged yet.
+    pub fn load(path: &Path) -> Result<Self, Md2msError> {
+        if !path.is_file() {
+            return Ok(History::default());
+        }
+        match std::fs::read_to_string(path) {
+            Ok(s) => History::parse(&s),
+            Err(e) => Err(Md2msError::HistoryError(e.to_string())),
+        }
+    }
+
+    /// Add an entry to the end of the history file.
+    pub fn append(path: &Path, entry: &Entry) -> Result<(), Md2msError> {
+        let line =
+            serde_json::to_string(entry).map_err(|e| Md2msError::HistoryError(e.to_string()))?;
+        std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .and_then(|mut file| writeln!(file, "{line}"))
+            .map_err(|e| Md2msError::HistoryError(e.to_string()))
+    }
+
+    /// The words written each day, from the first entry up to today. This is the change in the
+    /// word count since the day before, so it's negative on a day spent cutting. On the first day,
+    /// it's the change since the first entry.
+    pub fn daily(&self, today: NaiveDate) -> Vec<(NaiveDate, i64)> {
+        let Some(first) = self.entries.first() else {
+            return vec![];
+        };
+
+        // The last count logged on each day
+        let counts: BTreeMap<NaiveDate, usize> = self
+            .entries
+            .iter()
+            .map(|entry| (entry.date(), entry.words))
+            .collect();
+        let last = *counts.keys().last().unwrap_or(&today);
+
+        let mut days = vec![];
+        let mut previous = first.words as i64;
+        let mut day = first.date();
+        while day <= today.max(last) {
+            let written = match counts.get(&day) {
+                Some(words) => {
+                    let written = *words as i64 - previous;
+                    previous = *words as i64;
+                    written
+                }
+                None => 0,
+            };
+            days.push((day, written));
+            day += Duration::days(1);
+        }
+        days
+    }
+
+    /// The words written each week, by the Monday the week starts on
+    pub fn weekly(&self, today: NaiveDate) -> Vec<(NaiveDate, i64)> {
+        let mut weeks: Vec<(NaiveDate, i64)> = vec![];
+        for (day, written) in self.daily(today) {
+            let monday = day - Duration::days(day.weekday().num_days_from_monday() as i64);
+            match weeks.last_mut() {
+                Some((week, total)) if *week == monday => *total += written,
+                _ => weeks.push((monday, written)),
+            }
+        }
+        weeks
+    }
+
+    /// The current and longest runs of days with words written. Today doesn't break the current
+    /// streak until it's over.
+    pub fn streaks(&self, today: NaiveDate) -> (usize, usize) {
+        let daily = self.daily(today);
+
+        let mut longest = 0;
+        let mut run = 0;
+        for (_, written) in &daily {
+            run = if *written > 0 { run + 1 } else { 0 };
+            longest = longest.max(run);
+        }
+
+        let mut days = daily.iter().rev().peekable();
+        if days
+            .peek()
+            .is_some_and(|(day, written)| *day == today && *written <= 0)
+        {
+            days.next();
+        }
+        let current = days.take_while(|(_, written)| *written > 0).count();
+
+        (current, longest)
+    }
+
+    /// The words written per day and per week, the streaks, and a sparkline of the last `days`
+    /// days.
+    pub fn summary(&self, today: NaiveDate, days: usize, locale: &str) -> String {
+        let daily = self.daily(today);
+        if daily.is_empty() {
+            return "No word counts have been logged yet. Run `md2ms stats log` to start.\n"
+                .to_string();
+        }
+        let recent = &daily[daily.len().saturating_sub(days)..];
+
+        let mut s = String::from("Words written per day\n");
+        for (day, written) in recent {
+            s.push_str(&format!(
+                "{:<18}  {:>8}\n",
+                day.to_string(),
+                signed(*written, locale)
+            ));
+        }
+
+        s.push_str("\nWords written per week\n");
+        for (week, written) in self.weekly(today) {
+            s.push_str(&format!(
+                "{:<18}  {:>8}\n",
+                format!("Week of {week}"),
+                signed(written, locale)
+            ));
+        }
+
+        let (current, longest) = self.streaks(today);
+        s.push_str(&format!(
+            "\nCurrent streak: {}\nLongest streak: {}\n",
+            plural_days(current),
+            plural_days(longest)
+        ));
+
+        let values: Vec<i64> = recent.iter().map(|(_, written)| *written).collect();
+        s.push_str(&format!(
+            "Last {}: {}\n",
+            plural_days(recent.len()),
+            sparkline(&values)
+        ));
+        s
+    }
+}
+
+/// Draw the values as a sparkline. Days spent cutting are drawn as the lowest bar.
+pub fn sparkline(values: &[i64]) -> String {
+    let max = values.iter().copied().max().unwrap_or_default().max(0);
+    values
+        .iter()
+        .map(|value| {
+            if max == 0 {
+                return SPARKS[0];
+            }
+            let bar = value.max(&0) * (SPARKS.len() as i64 - 1) / max;
+            SPARKS[bar as usize]
+        })
+        .collect()
+}
+
+/// Format a change in the word count, with a minus sign if words were cut.
+pub fn signed(n: i64, locale: &str) -> String {
+    let words = separate_thousands(n.unsigned_abs() as usize, locale);
+    if n < 0 {
+        format!("-{words}")
+    } else {
+        words
+    }
+}
+
+fn plural_days(n: usize) -> String {
+    if n == 1 {
+        "1 day".to_string()
+    } else {
+        format!("{n} days")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn history() -> History {
+        History::parse(
+            r#"
+            {"timestamp":"2026-10-05T09:00:00-04:00","words":1000,"files":{"scene 1.md":1000}}
+            {"timestamp":"2026-10-05T21:00:00-04:00","words":1500,"files":{"scene 1.md":1500}}
+            {"timestamp":"2026-10-06T21:00:00-04:00","words":1400,"files":{"scene 1.md":1400}}
+            {"timestamp":"2026-10-08T22:00:00-04:00","words":2400,"files":{"scene 1.md":1400,"scene 2.md":1000}}
+            {"timestamp":"2026-10-12T23:30:00-04:00","words":2700,"files":{"scene 1.md":1400,"scene 2.md":1300}}
+            {"timestamp":"2026-10-13T20:00:00-04:00","words":3000,"files":{"scene 1.md":1400,"scene 2.md":1600}}
+            "#,
+        )
+        .unwrap()
+    }
+
+    fn date(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+    }
+
+    #[test]
+    fn test_entry_files() {
+        // A part with no scenes under it yet isn't logged as a file
+        let words = WordCount {
+            words: 300,
+            children: vec![
+                WordCount {
+                    label: "scene 1.md".to_string(),
+                    words: 300,
+                    scene: true,
+                    ..Default::default()
+                },
+                WordCount {
+                    label: "Act 2".to_string(),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+        let entry = Entry::new(&words);
+        assert_eq!(
+            entry.files,
+            BTreeMap::from([("scene 1.md".to_string(), 300)])
+        );
+    }
+
+    #[test]
+    fn test_daily() {
+        let daily = history().daily(date("2026-10-14"));
+        let written: Vec<i64> = daily.iter().map(|(_, written)| *written).collect();
+        assert_eq!(daily[0].0, date("2026-10-05"));
+        assert_eq!(written, vec![500, -100, 0, 1000, 0, 0, 0, 300, 300, 0]);
+
+        assert!(History::default().daily(date("2026-10-14")).is_empty());
+        assert!(History::parse("not json").is_err());
+    }
+
+    #[test]
+    fn test_weekly() {
+        let weekly = history().weekly(date("2026-10-14"));
+        assert_eq!(
+            weekly,
+            vec![(date("2026-10-05"), 1400), (date("2026-10-12"), 600)]
+        );
+    }
+
+    #[test]
+    fn test_streaks() {
+        // Nothing written yet today doesn't break the streak
+        assert_eq!(history().streaks(date("2026-10-14")), (2, 2));
+        assert_eq!(history().streaks(date("2026-10-15")), (0, 2));
+    }
+
+    #[test]
+    fn test_sparkline() {
+        assert_eq!(sparkline(&[0, 350, 700, -100]), "▁▄█▁");
+        assert_eq!(sparkline(&[0, 0]), "▁▁");
+    }
+}
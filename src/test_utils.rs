@@ -0,0 +1,43 @@
+// Helpers shared by the tests
+//
+use std::path::{Path, PathBuf};
+
+/// A folder of files for a test, which is removed when the test ends, whether or not it passed
+pub struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    /// Create the folder, named for the test so tests running at the same time don't share one,
+    /// with the given files in it. Anything left over from an earlier run is cleared out first.
+    pub fn new(name: &str, files: &[(&str, &str)]) -> Self {
+        let path = std::env::temp_dir().join(format!("md2ms-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+
+        let dir = TempDir { path };
+        for (file, content) in files {
+            dir.write(file, content);
+        }
+        dir
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Write a file, relative to the folder, creating the folders it's in
+    pub fn write(&self, file: &str, content: &str) {
+        let path = self.path.join(file);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).unwrap();
+        }
+        std::fs::write(path, content).unwrap();
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
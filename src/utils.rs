@@ -1,6 +1,8 @@
+use std::cmp::Ordering;
 use std::fs::File;
 use std::io::{self, Read};
 use std::path::{Path, PathBuf};
+use thousands::Separable;
 
 /// Round up to the nearest 100 or 500 (depending on length)
 /// Per Bill Shunn, round up to the nearest 100 words unless you're entering novella territory,
@@ -22,6 +24,41 @@ pub fn round_up(wc: usize) -> usize {
     wc
 }
 
+/// The last word of a name, for the running header, i.e., "Israel" for "Adam Israel"
+pub fn surname(name: &str) -> String {
+    name.split_whitespace()
+        .last()
+        .unwrap_or_default()
+        .to_string()
+}
+
+/// Format a number with the thousands separator used by the locale, i.e., 1,000 or 1.000
+pub fn separate_thousands(n: usize, locale: &str) -> String {
+    let language = locale
+        .split(['-', '_'])
+        .next()
+        .unwrap_or_default()
+        .to_lowercase();
+    match language.as_str() {
+        "da" | "de" | "el" | "es" | "id" | "it" | "nl" | "pt" | "ro" | "tr" => {
+            n.separate_with_dots()
+        }
+        "cs" | "fi" | "fr" | "hu" | "nb" | "no" | "pl" | "ru" | "sk" | "sv" | "uk" => {
+            n.separate_with_spaces()
+        }
+        _ => n.separate_with_commas(),
+    }
+}
+
+/// Escape the characters that have special meaning in XML
+pub fn escape_xml(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
 pub fn file_exists<P: AsRef<Path>>(filename: P) -> bool {
     let mut path = PathBuf::new();
     path.push(filename);
@@ -72,6 +109,114 @@ pub fn get_file_basedir(file: String) -> String {
     "".to_string()
 }
 
+/// Compare two strings the way a person would, so "scene 2" comes before "scene 10".
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (a_chunks, b_chunks) = (numeric_chunks(a), numeric_chunks(b));
+    for (x, y) in a_chunks.iter().zip(b_chunks.iter()) {
+        let ordering = match (x.parse::<u64>(), y.parse::<u64>()) {
+            (Ok(x), Ok(y)) => x.cmp(&y),
+            _ => x.to_lowercase().cmp(&y.to_lowercase()),
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+    a_chunks.len().cmp(&b_chunks.len()).then(a.cmp(b))
+}
+
+const ONES: [&str; 20] = [
+    "Zero",
+    "One",
+    "Two",
+    "Three",
+    "Four",
+    "Five",
+    "Six",
+    "Seven",
+    "Eight",
+    "Nine",
+    "Ten",
+    "Eleven",
+    "Twelve",
+    "Thirteen",
+    "Fourteen",
+    "Fifteen",
+    "Sixteen",
+    "Seventeen",
+    "Eighteen",
+    "Nineteen",
+];
+const TENS: [&str; 10] = [
+    "", "", "Twenty", "Thirty", "Forty", "Fifty", "Sixty", "Seventy", "Eighty", "Ninety",
+];
+
+/// Spell a number out, as it would be in a chapter heading, i.e., "Twenty-One"
+pub fn number_to_words(n: usize) -> String {
+    let (unit, size) = match n {
+        0..=19 => return ONES[n].to_string(),
+        20..=99 => {
+            return match n % 10 {
+                0 => TENS[n / 10].to_string(),
+                ones => format!("{}-{}", TENS[n / 10], ONES[ones]),
+            }
+        }
+        100..=999 => ("Hundred", 100),
+        _ => ("Thousand", 1000),
+    };
+    let words = format!("{} {unit}", number_to_words(n / size));
+    match n % size {
+        0 => words,
+        rest => format!("{words} {}", number_to_words(rest)),
+    }
+}
+
+/// Write a number in Roman numerals, i.e., "XIV"
+pub fn to_roman(n: usize) -> String {
+    const NUMERALS: [(usize, &str); 13] = [
+        (1000, "M"),
+        (900, "CM"),
+        (500, "D"),
+        (400, "CD"),
+        (100, "C"),
+        (90, "XC"),
+        (50, "L"),
+        (40, "XL"),
+        (10, "X"),
+        (9, "IX"),
+        (5, "V"),
+        (4, "IV"),
+        (1, "I"),
+    ];
+    let mut n = n;
+    let mut roman = String::new();
+    for (value, numeral) in NUMERALS {
+        while n >= value {
+            roman.push_str(numeral);
+            n -= value;
+        }
+    }
+    roman
+}
+
+/// Split a string into runs of digits and runs of everything else.
+fn numeric_chunks(s: &str) -> Vec<&str> {
+    let mut chunks = vec![];
+    let mut start = 0;
+    let mut digits = None;
+    for (i, c) in s.char_indices() {
+        let digit = c.is_ascii_digit();
+        if digits.is_some_and(|digits| digits != digit) {
+            chunks.push(&s[start..i]);
+            start = i;
+        }
+        digits = Some(digit);
+    }
+    if start < s.len() {
+        chunks.push(&s[start..]);
+    }
+    chunks
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -115,4 +260,56 @@ mod tests {
         let basedir = get_file_basedir(path.to_string());
         assert_eq!(basedir, "examples/novella_with_parts/Act 1/Chapter 1");
     }
+
+    #[test]
+    fn test_separate_thousands() {
+        assert_eq!(separate_thousands(12500, "en-US"), "12,500");
+        assert_eq!(separate_thousands(12500, "de_DE"), "12.500");
+        assert_eq!(separate_thousands(12500, "fr"), "12 500");
+        assert_eq!(separate_thousands(500, "fr"), "500");
+    }
+
+    #[test]
+    fn test_escape_xml() {
+        assert_eq!(
+            escape_xml("Tom & Jerry <\"cartoon\">"),
+            "Tom &amp; Jerry &lt;&quot;cartoon&quot;&gt;"
+        );
+    }
+
+    #[test]
+    fn test_natural_cmp() {
+        let mut scenes = vec![
+            "scene 10.md",
+            "Scene 2.md",
+            "scene 1.md",
+            "scene.md",
+            "scene 2a.md",
+        ];
+        scenes.sort_by(|a, b| natural_cmp(a, b));
+        assert_eq!(
+            scenes,
+            vec![
+                "scene 1.md",
+                "Scene 2.md",
+                "scene 2a.md",
+                "scene 10.md",
+                "scene.md"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_chapter_numbers() {
+        assert_eq!(number_to_words(1), "One");
+        assert_eq!(number_to_words(13), "Thirteen");
+        assert_eq!(number_to_words(40), "Forty");
+        assert_eq!(number_to_words(21), "Twenty-One");
+        assert_eq!(number_to_words(105), "One Hundred Five");
+        assert_eq!(number_to_words(2300), "Two Thousand Three Hundred");
+
+        assert_eq!(to_roman(4), "IV");
+        assert_eq!(to_roman(14), "XIV");
+        assert_eq!(to_roman(1994), "MCMXCIV");
+    }
 }
@@ -0,0 +1,476 @@
+// Word count reports, broken down by part, chapter and scene
+//
+use clap::ValueEnum;
+
+use crate::manuscript::Manuscript;
+use crate::utils::{round_up, separate_thousands};
+
+/// How to lay out the word count breakdown
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum Breakdown {
+    /// An indented tree, with subtotals for each part and chapter
+    Tree,
+    /// A table with a row per scene, followed by the subtotals
+    Table,
+}
+
+/// The number of words in a part of the manuscript, and in each of its children
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct WordCount {
+    pub label: String,
+    pub words: usize,
+
+    /// The number of words the author is aiming for, from `word_goal`
+    pub goal: Option<usize>,
+
+    /// Whether this is a scene, rather than a part, chapter or the whole manuscript. A part or
+    /// chapter can have no children of its own, but it still isn't a scene.
+    pub scene: bool,
+
+    pub children: Vec<WordCount>,
+}
+
+impl WordCount {
+    /// Count the words in the manuscript, in include order.
+    ///
+    /// Parts and chapters without a heading or a goal aren't given a level of their own; their
+    /// children are rolled up into the level above instead.
+    pub fn new(manuscript: &Manuscript) -> Self {
+        let mut root = WordCount {
+            label: manuscript.title_page.title.clone(),
+            goal: manuscript.word_goal,
+            ..Default::default()
+        };
+        for part in &manuscript.parts {
+            let mut chapters: Vec<WordCount> = vec![];
+            for chapter in &part.chapters {
+                let scenes: Vec<WordCount> = chapter
+                    .scenes
+                    .iter()
+                    .map(|scene| WordCount {
+                        label: scene.source.clone(),
+                        words: scene.word_count,
+                        goal: scene.word_goal,
+                        scene: true,
+                        children: vec![],
+                    })
+                    .collect();
+                chapters.extend(WordCount::group(
+                    chapter.heading.clone(),
+                    &chapter.folder,
+                    chapter.word_goal,
+                    scenes,
+                ));
+            }
+            root.children.extend(WordCount::group(
+                part.heading.clone(),
+                &part.folder,
+                part.word_goal,
+                chapters,
+            ));
+        }
+        root.words = root.children.iter().map(|c| c.words).sum();
+        root
+    }
+
+    /// Group the children under a heading, or pass them through if there isn't one. A folder with
+    /// a goal of its own is grouped under the folder's name instead, so the goal isn't lost.
+    fn group(
+        heading: Option<String>,
+        folder: &str,
+        goal: Option<usize>,
+        children: Vec<WordCount>,
+    ) -> Vec<WordCount> {
+        let label =
+            heading.or_else(|| (goal.is_some() && !folder.is_empty()).then(|| folder.to_string()));
+        match label {
+            Some(label) => vec![WordCount {
+                label,
+                words: children.iter().map(|c| c.words).sum(),
+                goal,
+                scene: false,
+                children,
+            }],
+            None => children,
+        }
+    }
+
+    /// How far along the goal is, as a percentage
+    pub fn percent(&self) -> Option<usize> {
+        self.goal
+            .filter(|goal| *goal > 0)
+            .map(|goal| self.words * 100 / goal)
+    }
+
+    /// The progress towards the goal, i.e. "45% of 600", or nothing if there's no goal
+    fn progress(&self, locale: &str) -> String {
+        match (self.goal, self.percent()) {
+            (Some(goal), Some(percent)) => {
+                format!("{percent}% of {}", separate_thousands(goal, locale))
+            }
+            _ => String::new(),
+        }
+    }
+
+    /// The scenes, in include order
+    pub fn scenes(&self) -> Vec<&WordCount> {
+        if self.scene {
+            return vec![self];
+        }
+        self.children.iter().flat_map(|c| c.scenes()).collect()
+    }
+
+    /// The summary printed after every word count: the exact count, the rounded figure used on the
+    /// title page, and the progress towards the word goals.
+    pub fn summary(&self, locale: &str) -> String {
+        let mut s = format!(
+            "Exact word count: {}\nManuscript word count: {}\n",
+            separate_thousands(self.words, locale),
+            separate_thousands(round_up(self.words), locale)
+        );
+        if self.goal.is_some() {
+            s.push_str(&format!("Word goal: {}\n", self.progress(locale)));
+        }
+
+        let scenes = self.scenes();
+        let with_goals: Vec<&&WordCount> = scenes.iter().filter(|s| s.goal.is_some()).collect();
+        for (title, under) in [("Under", true), ("Over", false)] {
+            let matching: Vec<String> = with_goals
+                .iter()
+                .filter(|scene| match scene.goal {
+                    Some(goal) if under => scene.words < goal,
+                    Some(goal) => scene.words > goal,
+                    None => false,
+                })
+                .map(|scene| {
+                    format!(
+                        "  {}: {} ({})\n",
+                        scene.label,
+                        separate_thousands(scene.words, locale),
+                        scene.progress(locale)
+                    )
+                })
+                .collect();
+            if !matching.is_empty() {
+                s.push_str(&format!("{title} target:\n{}", matching.concat()));
+            }
+        }
+        s
+    }
+
+    /// The word count of a single scene, i.e. the file open in Obsidian
+    pub fn scene_summary(&self, scene: &str, locale: &str) -> String {
+        match self.scenes().into_iter().find(|s| s.label == scene) {
+            Some(wc) => {
+                let mut s = format!(
+                    "Scene word count ({scene}): {}",
+                    separate_thousands(wc.words, locale)
+                );
+                if wc.goal.is_some() {
+                    s.push_str(&format!(", {}", wc.progress(locale)));
+                }
+                s.push('\n');
+                s
+            }
+            None => format!("{scene} isn't included in the manuscript\n"),
+        }
+    }
+
+    /// The depth of the deepest level below this one
+    fn depth(&self) -> usize {
+        self.children
+            .iter()
+            .map(|c| c.depth() + 1)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Render the counts as an indented tree, i.e.
+    ///
+    /// ```text
+    /// Novella                     7,209
+    /// ├── Act 1                   2,403
+    /// │   ├── Chapter 1             801
+    /// │   │   ├── scene 1.md        267
+    /// ```
+    pub fn tree(&self, locale: &str) -> String {
+        let mut rows: Vec<(String, &WordCount)> = vec![(self.label.clone(), self)];
+        self.tree_rows("", &mut rows);
+        render_rows(&rows, locale)
+    }
+
+    fn tree_rows<'a>(&'a self, prefix: &str, rows: &mut Vec<(String, &'a WordCount)>) {
+        for (i, child) in self.children.iter().enumerate() {
+            let last = i == self.children.len() - 1;
+            let branch = if last { "└── " } else { "├── " };
+            rows.push((format!("{prefix}{branch}{}", child.label), child));
+            let indent = if last { "    " } else { "│   " };
+            child.tree_rows(&format!("{prefix}{indent}"), rows);
+        }
+    }
+
+    /// Render the counts as a Markdown table, with a row per scene and a subtotal after each part
+    /// and chapter.
+    pub fn table(&self, locale: &str) -> String {
+        let columns = self.depth();
+        let mut header: Vec<String> = vec![String::new(); columns];
+        if let Some(last) = header.last_mut() {
+            *last = "Scene".to_string();
+        }
+        if columns > 1 {
+            header[columns - 2] = "Chapter".to_string();
+        }
+        if columns > 2 {
+            header[0] = "Part".to_string();
+        }
+        header.push("Words".to_string());
+        header.push("Goal".to_string());
+
+        let mut rows: Vec<Vec<String>> = vec![header];
+        self.table_rows(&mut vec![], columns, locale, &mut rows);
+        let mut total = vec![String::new(); columns];
+        total[0] = "Total".to_string();
+        total.push(separate_thousands(self.words, locale));
+        total.push(self.progress(locale));
+        rows.push(total);
+
+        // Pad every column to the width of its widest cell, with the words right-aligned
+        let widths: Vec<usize> = (0..=columns + 1)
+            .map(|i| rows.iter().map(|r| r[i].chars().count()).max().unwrap_or(0))
+            .collect();
+        let mut table = String::new();
+        for (n, row) in rows.iter().enumerate() {
+            let cells: Vec<String> = row
+                .iter()
+                .enumerate()
+                .map(|(i, cell)| {
+                    if i == columns {
+                        format!("{cell:>width$}", width = widths[i])
+                    } else {
+                        format!("{cell:<width$}", width = widths[i])
+                    }
+                })
+                .collect();
+            table.push_str(&format!("| {} |\n", cells.join(" | ")));
+            if n == 0 {
+                let rules: Vec<String> = widths
+                    .iter()
+                    .enumerate()
+                    .map(|(i, width)| {
+                        if i == columns {
+                            format!("{}:", "-".repeat(*width - 1))
+                        } else {
+                            "-".repeat(*width)
+                        }
+                    })
+                    .collect();
+                table.push_str(&format!("| {} |\n", rules.join(" | ")));
+            }
+        }
+        table
+    }
+
+    fn table_rows(
+        &self,
+        path: &mut Vec<String>,
+        columns: usize,
+        locale: &str,
+        rows: &mut Vec<Vec<String>>,
+    ) {
+        for child in &self.children {
+            path.push(child.label.clone());
+            if child.scene {
+                // Scenes always go in the last column, even if they aren't in a chapter
+                let mut row = path[..path.len() - 1].to_vec();
+                row.resize(columns - 1, String::new());
+                row.push(child.label.clone());
+                row.push(separate_thousands(child.words, locale));
+                row.push(child.progress(locale));
+                rows.push(row);
+            } else {
+                child.table_rows(path, columns, locale, rows);
+                let mut row = vec![String::new(); columns];
+                row[path.len() - 1] = format!("{} total", child.label);
+                row.push(separate_thousands(child.words, locale));
+                row.push(child.progress(locale));
+                rows.push(row);
+            }
+            path.pop();
+        }
+    }
+}
+
+/// Lay out the labels and counts, with the counts right-aligned in a column, followed by the
+/// progress towards the goal, if there is one
+fn render_rows(rows: &[(String, &WordCount)], locale: &str) -> String {
+    let counts: Vec<String> = rows
+        .iter()
+        .map(|(_, wc)| separate_thousands(wc.words, locale))
+        .collect();
+    let label_width = rows
+        .iter()
+        .map(|(label, _)| label.chars().count())
+        .max()
+        .unwrap_or(0);
+    let count_width = counts.iter().map(|c| c.len()).max().unwrap_or(0);
+
+    let mut s = String::new();
+    for ((label, wc), count) in rows.iter().zip(counts) {
+        let padding = label_width - label.chars().count();
+        let line = format!(
+            "{label}{}  {count:>count_width$}  {}",
+            " ".repeat(padding),
+            wc.progress(locale)
+        );
+        s.push_str(line.trim_end());
+        s.push('\n');
+    }
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manuscript::{Chapter, Part, Scene, TitlePage};
+
+    fn scene(source: &str, word_count: usize, word_goal: usize) -> Scene {
+        Scene {
+            source: source.to_string(),
+            word_count,
+            word_goal: Some(word_goal),
+            ..Default::default()
+        }
+    }
+
+    fn manuscript() -> Manuscript {
+        Manuscript {
+            title_page: TitlePage {
+                title: "Story".to_string(),
+                ..Default::default()
+            },
+            word_goal: Some(2000),
+            parts: vec![Part {
+                heading: Some("Act 1".to_string()),
+                word_goal: Some(1500),
+                chapters: vec![
+                    Chapter {
+                        heading: Some("Chapter 1".to_string()),
+                        scenes: vec![scene("one.md", 1200, 1000), scene("two.md", 300, 400)],
+                        ..Default::default()
+                    },
+                    Chapter {
+                        heading: Some("Chapter 2".to_string()),
+                        scenes: vec![scene("three.md", 50, 50)],
+                        ..Default::default()
+                    },
+                ],
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_word_count_tree() {
+        let wc = WordCount::new(&manuscript());
+        assert_eq!(wc.words, 1550);
+        assert_eq!(wc.children[0].children[0].words, 1500);
+        assert_eq!(
+            wc.tree("en-US"),
+            concat!(
+                "Story                 1,550  77% of 2,000\n",
+                "└── Act 1             1,550  103% of 1,500\n",
+                "    ├── Chapter 1     1,500\n",
+                "    │   ├── one.md    1,200  120% of 1,000\n",
+                "    │   └── two.md      300  75% of 400\n",
+                "    └── Chapter 2        50\n",
+                "        └── three.md     50  100% of 50\n",
+            )
+        );
+    }
+
+    #[test]
+    fn test_word_count_table() {
+        let wc = WordCount::new(&manuscript());
+        assert_eq!(
+            wc.table("en-US"),
+            concat!(
+                "| Part        | Chapter         | Scene    | Words | Goal          |\n",
+                "| ----------- | --------------- | -------- | ----: | ------------- |\n",
+                "| Act 1       | Chapter 1       | one.md   | 1,200 | 120% of 1,000 |\n",
+                "| Act 1       | Chapter 1       | two.md   |   300 | 75% of 400    |\n",
+                "|             | Chapter 1 total |          | 1,500 |               |\n",
+                "| Act 1       | Chapter 2       | three.md |    50 | 100% of 50    |\n",
+                "|             | Chapter 2 total |          |    50 |               |\n",
+                "| Act 1 total |                 |          | 1,550 | 103% of 1,500 |\n",
+                "| Total       |                 |          | 1,550 | 77% of 2,000  |\n",
+            )
+        );
+    }
+
+    #[test]
+    fn test_headings_are_optional() {
+        let mut ms = manuscript();
+        ms.parts[0].heading = None;
+        ms.parts[0].chapters[1].heading = None;
+        let wc = WordCount::new(&ms);
+        assert_eq!(wc.children.len(), 2);
+        assert_eq!(wc.children[0].label, "Chapter 1");
+        assert_eq!(wc.children[1].label, "three.md");
+        assert!(wc
+            .table("en-US")
+            .contains("|                 | three.md |    50 |"));
+    }
+
+    #[test]
+    fn test_word_count_summary() {
+        let wc = WordCount::new(&manuscript());
+        assert_eq!(
+            wc.summary("en-US"),
+            concat!(
+                "Exact word count: 1,550\n",
+                "Manuscript word count: 1,600\n",
+                "Word goal: 77% of 2,000\n",
+                "Under target:\n",
+                "  two.md: 300 (75% of 400)\n",
+                "Over target:\n",
+                "  one.md: 1,200 (120% of 1,000)\n",
+            )
+        );
+    }
+
+    #[test]
+    fn test_scene_summary() {
+        let wc = WordCount::new(&manuscript());
+        assert_eq!(
+            wc.scene_summary("two.md", "en-US"),
+            "Scene word count (two.md): 300, 75% of 400\n"
+        );
+        assert_eq!(
+            wc.scene_summary("notes.md", "en-US"),
+            "notes.md isn't included in the manuscript\n"
+        );
+    }
+
+    #[test]
+    fn test_empty_chapter_folder() {
+        // A chapter folder without any scenes in it yet
+        let mut ms = manuscript();
+        ms.parts[0].chapters.push(Chapter {
+            heading: Some("Chapter 3".to_string()),
+            folder: "Act 1/Chapter 3".to_string(),
+            word_goal: Some(500),
+            ..Default::default()
+        });
+        let wc = WordCount::new(&ms);
+        let scenes: Vec<&str> = wc.scenes().iter().map(|s| s.label.as_str()).collect();
+        assert_eq!(scenes, ["one.md", "two.md", "three.md"]);
+        assert!(!wc.summary("en-US").contains("Chapter 3"));
+        assert!(wc
+            .table("en-US")
+            .contains("|             | Chapter 3 total |          |     0 | 0% of 500     |"));
+
+        // Nor is an empty manuscript a scene
+        assert!(WordCount::new(&Manuscript::default()).scenes().is_empty());
+    }
+}